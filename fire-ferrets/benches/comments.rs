@@ -0,0 +1,114 @@
+//! Time programs that pass a big comment around through variables, the stack, lists and maps,
+//! on both the tree-walking interpreter and the VM. The `copy` group compares copying a comment
+//! value, which only shares it, with copying its text, which is what every copy used to do.
+//! Run with `cargo bench --bench comments`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use fire_ferrets::{
+    interpreter::{
+        program::{Line, Program},
+        value::Value,
+        Interpreter,
+    },
+    parser::Parser,
+};
+
+/// A comment literal with `len` lines of code in it
+fn big_comment(len: usize) -> String {
+    let lines: Vec<_> = (0..len)
+        .map(|i| {
+            format!(
+                "set line{} \"{} ferrets were here, and they left a long line behind\"",
+                i, i
+            )
+        })
+        .collect();
+    format!("{{{}}}", lines.join("\n"))
+}
+
+/// Copy a big comment between variables and through the stack
+fn pass_comments() -> String {
+    format!(
+        "{}
+set big pop
+set n 0
+{{set twin big
+push twin
+push big
+swap
+drop
+set other pop
+set n n + 1
+n < 1000 ? uncomment loop}}
+set loop pop
+uncomment loop",
+        big_comment(2000)
+    )
+}
+
+/// Call a comment that keeps putting a big comment in lists and maps
+fn call_comments() -> String {
+    format!(
+        "{}
+set big pop
+set xs [big, big]
+{{append xs big
+set m [\"big\": big, \"xs\": xs]
+set n n + 1}}
+set step pop
+set n 0
+{{call step
+n < 500 ? uncomment loop}}
+set loop pop
+uncomment loop",
+        big_comment(200)
+    )
+}
+
+/// A fresh interpreter along with `source`, ready to be run
+fn setup(source: &str, vm: bool) -> (Interpreter, Program) {
+    let (lines, errors) = Parser::new(source).parse_all();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let mut lines: Vec<_> = lines
+        .into_iter()
+        .map(|(stmt, source)| Line::new(stmt, source))
+        .collect();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_vm(vm);
+    assert!(interpreter.resolve(&mut lines).errors.is_empty());
+    (interpreter, Program::new(lines))
+}
+
+fn bench_programs(c: &mut Criterion) {
+    let programs = [
+        ("pass_comments", pass_comments()),
+        ("call_comments", call_comments()),
+    ];
+    for (name, source) in &programs {
+        let mut group = c.benchmark_group(*name);
+        for &(mode, vm) in &[("tree-walker", false), ("vm", true)] {
+            group.bench_function(mode, |b| {
+                b.iter_batched(
+                    || setup(source, vm),
+                    |(mut interpreter, program)| interpreter.run(program).unwrap(),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+fn bench_copy(c: &mut Criterion) {
+    let text = big_comment(2000);
+    let text = &text[1..text.len() - 1];
+    let comment = Value::comment(text);
+    let mut group = c.benchmark_group("copy");
+    group.bench_function("shared", |b| b.iter(|| comment.clone()));
+    group.bench_function("deep", |b| b.iter(|| Value::comment(text)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_programs, bench_copy);
+criterion_main!(benches);
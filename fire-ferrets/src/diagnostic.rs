@@ -0,0 +1,152 @@
+use std::fmt::Write;
+
+use crate::{error::FerError, lexer::Span};
+
+/// An error message ready to be shown to the user,
+/// rendered with the offending source line and the span underlined
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+    /// Warnings are about code that might still run fine
+    pub warning: bool,
+}
+
+impl Diagnostic {
+    pub fn new(message: String, span: Span) -> Self {
+        Self {
+            message,
+            span,
+            notes: vec![],
+            help: None,
+            warning: false,
+        }
+    }
+
+    /// Create a diagnostic for `error`, which happened in `source`
+    pub fn from_error(error: &FerError, source: &str) -> Self {
+        let diagnostic = Self::new(error.to_string(), error.span());
+        match error {
+            FerError::Lex(span) => match source.get(span.start..).and_then(|s| s.chars().next()) {
+                Some('"') => diagnostic.with_help("this string is never closed"),
+                _ => diagnostic,
+            },
+            _ => diagnostic,
+        }
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    pub fn into_warning(mut self) -> Self {
+        self.warning = true;
+        self
+    }
+
+    /// Render the diagnostic, `filename` is the name of the file `source` is from
+    pub fn render(&self, filename: &str, source: &str) -> String {
+        let (line, column) = self.span.get_line_and_column(source);
+        let text = source.lines().nth(line - 1).unwrap_or("");
+        let gutter = " ".repeat(line.to_string().len());
+
+        // everything is measured in characters, with tabs expanded so the underline lines up
+        let width = |s: &str| {
+            s.chars()
+                .map(|c| if c == '\t' { 4 } else { 1 })
+                .sum::<usize>()
+        };
+        let before: String = text.chars().take(column - 1).collect();
+        let underlined: String = text
+            .chars()
+            .skip(column - 1)
+            .take(self.span.len())
+            .collect();
+
+        let mut out = String::new();
+        // writing to a `String` can't fail
+        let level = if self.warning { "warning" } else { "error" };
+        writeln!(out, "{}: {}", level, self.message).unwrap();
+        writeln!(out, "{}--> {}:{}:{}", gutter, filename, line, column).unwrap();
+        writeln!(out, "{} |", gutter).unwrap();
+        writeln!(out, "{} | {}", line, text.replace('\t', "    ")).unwrap();
+        writeln!(
+            out,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(width(&before)),
+            "^".repeat(width(&underlined).max(1))
+        )
+        .unwrap();
+        if !self.notes.is_empty() || self.help.is_some() {
+            writeln!(out, "{} |", gutter).unwrap();
+        }
+        for note in &self.notes {
+            writeln!(out, "{} = note: {}", gutter, note).unwrap();
+        }
+        if let Some(help) = &self.help {
+            writeln!(out, "{} = help: {}", gutter, help).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Diagnostic;
+    use crate::{error::FerError, lexer::Span};
+
+    #[test]
+    fn test_render() {
+        let source = "print 1\nprint x + 2\n";
+        let diagnostic = Diagnostic::new("x is undefined".to_string(), Span { start: 14, end: 15 })
+            .with_note("a note")
+            .with_help("some help");
+        assert_eq!(
+            diagnostic.render("test.fer", source),
+            "error: x is undefined
+ --> test.fer:2:7
+  |
+2 | print x + 2
+  |       ^
+  |
+  = note: a note
+  = help: some help
+"
+        );
+    }
+
+    #[test]
+    fn test_render_multiline_span() {
+        let source = "{print 1\nprint 2}";
+        let diagnostic = Diagnostic::new("oops".to_string(), Span { start: 0, end: 17 });
+        assert_eq!(
+            diagnostic.render("test.fer", source),
+            "error: oops
+ --> test.fer:1:1
+  |
+1 | {print 1
+  | ^^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn test_unclosed_string_help() {
+        let source = "print \"abc\n";
+        let error = FerError::Lex(Span { start: 6, end: 7 });
+        let diagnostic = Diagnostic::from_error(&error, source);
+        assert_eq!(
+            diagnostic.help,
+            Some("this string is never closed".to_string())
+        );
+    }
+}
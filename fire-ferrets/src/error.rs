@@ -0,0 +1,50 @@
+use std::{fmt, rc::Rc};
+
+use crate::lexer::Span;
+
+/// Any error that can happen while lexing, parsing or running a program
+#[derive(Clone, Debug, PartialEq)]
+pub enum FerError {
+    /// Input that the lexer couldn't turn into a token
+    Lex(Span),
+    /// Input that isn't a valid program
+    Parse(Span, String),
+    /// An error that happened while running the program
+    Runtime(Span, String),
+    /// Errors in a file brought in by the `include` statement at the span
+    Included(Span, Rc<SourceFile>, Vec<FerError>),
+}
+
+/// A file of source code, for reporting errors in it
+#[derive(Debug, PartialEq)]
+pub struct SourceFile {
+    pub name: String,
+    pub contents: String,
+}
+
+pub type FerResult<T> = Result<T, FerError>;
+
+impl FerError {
+    /// The span of source code that caused the error
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Lex(span)
+            | Self::Parse(span, _)
+            | Self::Runtime(span, _)
+            | Self::Included(span, _, _) => *span,
+        }
+    }
+}
+
+impl fmt::Display for FerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lex(_) => write!(f, "Unrecognized token"),
+            Self::Parse(_, msg) | Self::Runtime(_, msg) => write!(f, "{}", msg),
+            Self::Included(_, file, errors) => match errors.as_slice() {
+                [error] => write!(f, "{} (in {})", error, file.name),
+                _ => write!(f, "{} errors in {}", errors.len(), file.name),
+            },
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::{convert::TryFrom, rc::Rc};
+
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use super::{index, int::Overflow, value::Value, Interpreter, ValueResult, Visitor};
+use crate::{
+    error::{FerError, FerResult},
+    lexer::Span,
+    parser::Expr,
+};
+
+impl Interpreter {
+    /// Evaluate the arguments of a builtin function call and run it
+    pub(super) fn visit_builtin_call(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        span: Span,
+    ) -> ValueResult {
+        let args = args
+            .iter()
+            .map(|arg| self.visit_expr(arg))
+            .collect::<FerResult<Vec<_>>>()?;
+        self.call_builtin(name, args, span)
+    }
+
+    /// Run the builtin function `name` on arguments that have already been evaluated
+    pub(super) fn call_builtin(&mut self, name: &str, args: Vec<Value>, span: Span) -> ValueResult {
+        match name {
+            "int" => {
+                let [value] = arity(name, args, span)?;
+                self.convert_to_int(value, span)
+            }
+            "float" => {
+                let [value] = arity(name, args, span)?;
+                convert_to_float(value, span)
+            }
+            "eof" => {
+                let [] = arity(name, args, span)?;
+                self.input
+                    .at_eof()
+                    .map(Value::Bool)
+                    .map_err(|e| FerError::Runtime(span, format!("Couldn't read input: {}", e)))
+            }
+            "readfile" => {
+                let [path] = arity(name, args, span)?;
+                self.read_file(path, span)
+            }
+            "exists" => {
+                let [path] = arity(name, args, span)?;
+                self.file_exists(path, span)
+            }
+            "len" => {
+                let [value] = arity(name, args, span)?;
+                len(value, span)
+            }
+            "keys" => match arity(name, args, span)? {
+                [Value::Map(entries)] => {
+                    let keys = entries.keys().map(|key| Value::Str(key.as_str().into()));
+                    Ok(Value::List(Rc::new(keys.collect())))
+                }
+                [value] => Err(FerError::Runtime(
+                    span,
+                    format!("Cannot get the keys of a {}", value.type_name()),
+                )),
+            },
+            "has" => match arity(name, args, span)? {
+                [Value::Map(entries), key] => {
+                    Ok(Value::Bool(entries.contains_key(index::key(&key, span)?)))
+                }
+                [value, _] => Err(FerError::Runtime(
+                    span,
+                    format!("Cannot look up keys in a {}", value.type_name()),
+                )),
+            },
+            _ => Err(FerError::Runtime(
+                span,
+                format!("{} is not a builtin function", name),
+            )),
+        }
+    }
+
+    /// Floats are truncated towards zero, strings are parsed
+    fn convert_to_int(&self, value: Value, span: Span) -> ValueResult {
+        match value {
+            Value::Int(_) | Value::BigInt(_) => Ok(value),
+            Value::Float(x) if x.is_finite() => {
+                let x = x.trunc();
+                match x.to_i64() {
+                    Some(n) => Ok(Value::Int(n)),
+                    None => self.float_overflow(x, span),
+                }
+            }
+            Value::Str(ref s) => match s.trim().parse() {
+                Ok(n) => Ok(Value::Int(n)),
+                Err(_) => Err(conversion_error(&value, "an integer", span)),
+            },
+            _ => Err(conversion_error(&value, "an integer", span)),
+        }
+    }
+
+    /// Convert a whole float outside the range of `i64` according to the overflow mode
+    fn float_overflow(&self, x: f64, span: Span) -> ValueResult {
+        // finite floats always convert
+        let n = BigInt::from_f64(x).unwrap();
+        match self.overflow {
+            Overflow::Checked => Err(FerError::Runtime(
+                span,
+                format!("{:?} is too large to convert to an integer", x),
+            )),
+            Overflow::Wrapping => {
+                let low_bits = n & BigInt::from(u64::MAX);
+                Ok(Value::Int(low_bits.to_u64().unwrap() as i64))
+            }
+            // `as` saturates
+            Overflow::Saturating => Ok(Value::Int(x as i64)),
+            Overflow::BigInt => Ok(Value::BigInt(n)),
+        }
+    }
+}
+
+/// Strings are parsed, integers may lose precision
+fn convert_to_float(value: Value, span: Span) -> ValueResult {
+    match value {
+        Value::Str(ref s) => match s.trim().parse() {
+            Ok(x) => Ok(Value::Float(x)),
+            Err(_) => Err(conversion_error(&value, "a float", span)),
+        },
+        _ => match value.to_float() {
+            Some(x) => Ok(Value::Float(x)),
+            None => Err(conversion_error(&value, "a float", span)),
+        },
+    }
+}
+
+/// The number of elements in a list or map, or characters in a string
+fn len(value: Value, span: Span) -> ValueResult {
+    let len = match &value {
+        Value::List(items) => items.len(),
+        Value::Map(entries) => entries.len(),
+        Value::Str(s) => s.chars().count(),
+        _ => {
+            return Err(FerError::Runtime(
+                span,
+                format!("Cannot take the length of a {}", value.type_name()),
+            ))
+        }
+    };
+    Ok(Value::Int(len as i64))
+}
+
+/// Check that a builtin got exactly `N` arguments
+fn arity<const N: usize>(name: &str, args: Vec<Value>, span: Span) -> FerResult<[Value; N]> {
+    <[Value; N]>::try_from(args).map_err(|args| {
+        FerError::Runtime(
+            span,
+            format!("{} takes {} argument(s), got {}", name, N, args.len()),
+        )
+    })
+}
+
+fn conversion_error(value: &Value, to: &str, span: Span) -> FerError {
+    FerError::Runtime(span, format!("Cannot convert {} to {}", value.repr(), to))
+}
@@ -0,0 +1,383 @@
+use std::{convert::TryFrom, rc::Rc};
+
+use super::{value::Value, Interpreter};
+use crate::{
+    lexer::{Span, TokenKind},
+    parser::{Expr, ExprKind, HalfSpanLit, SpanLit, Stmt, StmtKind, Var},
+    resolver::Slot,
+};
+
+/// An index into one of a chunk's tables, or a jump target
+pub type Index = u32;
+
+/// A single VM instruction.
+/// Expressions are evaluated on an operand stack of their own,
+/// the Ferette stack is only touched by the ops that work on it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Op {
+    /// Push `constants[n]`
+    Const(Index),
+    /// Push the value of a variable
+    Load(Slot),
+    /// Pop a value off the Ferette stack and push it
+    PopStack,
+    Binary(TokenKind),
+    Unary(TokenKind),
+    /// Turn the operand on top into a boolean
+    Truthy,
+    /// If the truthiness of the operand on top is the given boolean, replace it with that
+    /// boolean and jump, otherwise pop it. Used for `and` and `or`.
+    ShortCircuit(bool, Index),
+    /// Pop an operand and jump if it's falsy
+    JumpUnless(Index),
+    /// Call the builtin `names[n]` with this many arguments
+    Builtin(Index, Index),
+    Depth,
+    Peek,
+    /// Check that the operand on top can be used as an offset into the Ferette stack
+    Offset(Offset),
+    StackIndex,
+    /// Make a list out of this many operands
+    List(Index),
+    /// Check that the second operand from the top can be used as a map key
+    MapKey,
+    /// Make a map out of this many pairs of operands
+    Map(Index),
+    Index,
+    /// Slice an operand, the booleans say whether there are operands for the start and end
+    Slice(bool, bool),
+
+    Set(Slot),
+    /// Assign to an element of a variable, with this many indices below the value
+    SetIndex(Slot, Index),
+    Append(Slot),
+    Remove(Slot),
+    Push,
+    Drop,
+    Dup,
+    Swap,
+    Over,
+    Rot,
+    Clear,
+    Pick,
+    Print,
+    Read(Slot),
+    ReadInt(Slot),
+    /// Check that the operand on top can be used as a file path
+    Path,
+    /// Write to a file, appending if the boolean is set
+    WriteFile(bool),
+    /// Comment out the lines between `addresses[n]` and `addresses[n + 1]`
+    CommentOut(Index),
+    /// Uncomment the comment in `vars[n]`
+    Uncomment(Index),
+    /// Copy the comment in `vars[n]` to `addresses[m]`
+    Copy(Index, Index),
+    Move(Index, Index),
+    Call(Index),
+    /// Include the file `names[n]`, as a module if there's a slot for it
+    Include(Index, Option<Slot>),
+}
+
+/// What an offset into the stack is for, to explain errors
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Offset {
+    StackIndex,
+    Pick,
+}
+
+impl Offset {
+    pub fn what(self) -> &'static str {
+        match self {
+            Offset::StackIndex => "A stack index",
+            Offset::Pick => "The argument of pick",
+        }
+    }
+}
+
+/// The compiled form of a single line
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    /// The span of source code each op came from, for errors
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    /// Builtin functions and file paths
+    pub names: Vec<String>,
+    /// Comments named by comment operations, which need their names for errors
+    pub vars: Vec<Var>,
+    pub addresses: Vec<HalfSpanLit>,
+}
+
+impl Chunk {
+    pub fn compile(stmt: &Stmt) -> Self {
+        let mut chunk = Self::default();
+        chunk.stmt(stmt);
+        chunk
+    }
+
+    fn emit(&mut self, op: Op, span: Span) -> Index {
+        self.code.push(op);
+        self.spans.push(span);
+        index(self.code.len() - 1)
+    }
+
+    /// Point the jump at `jump` to the next op to be emitted
+    fn patch(&mut self, jump: Index) {
+        let target = index(self.code.len());
+        match &mut self.code[jump as usize] {
+            Op::ShortCircuit(_, to) | Op::JumpUnless(to) => *to = target,
+            op => unreachable!("{:?} isn't a jump", op),
+        }
+    }
+
+    fn constant(&mut self, value: Value) -> Index {
+        self.constants.push(value);
+        index(self.constants.len() - 1)
+    }
+
+    fn name(&mut self, name: &str) -> Index {
+        match self.names.iter().position(|n| n == name) {
+            Some(i) => index(i),
+            None => {
+                self.names.push(name.to_string());
+                index(self.names.len() - 1)
+            }
+        }
+    }
+
+    fn var(&mut self, var: &Var) -> Index {
+        self.vars.push(var.clone());
+        index(self.vars.len() - 1)
+    }
+
+    fn address(&mut self, address: HalfSpanLit) -> Index {
+        self.addresses.push(address);
+        index(self.addresses.len() - 1)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let span = stmt.span;
+        let op = match &stmt.kind {
+            StmtKind::Set(var, expr) => {
+                self.expr(expr);
+                Op::Set(var.slot())
+            }
+            StmtKind::SetIndex(var, path, expr) => {
+                for index in path {
+                    self.expr(index);
+                }
+                self.expr(expr);
+                Op::SetIndex(var.slot(), index(path.len()))
+            }
+            StmtKind::Append(var, expr) => {
+                self.expr(expr);
+                Op::Append(var.slot())
+            }
+            StmtKind::Remove(var, expr) => {
+                self.expr(expr);
+                Op::Remove(var.slot())
+            }
+            StmtKind::Push(expr) => {
+                self.expr(expr);
+                Op::Push
+            }
+            StmtKind::Check(expr) => {
+                self.expr(expr);
+                self.emit(Op::Truthy, span);
+                Op::Push
+            }
+            StmtKind::Pop | StmtKind::Drop => Op::Drop,
+            StmtKind::Dup => Op::Dup,
+            StmtKind::Swap => Op::Swap,
+            StmtKind::Over => Op::Over,
+            StmtKind::Rot => Op::Rot,
+            StmtKind::Clear => Op::Clear,
+            StmtKind::Pick(expr) => {
+                self.expr(expr);
+                self.emit(Op::Offset(Offset::Pick), expr.span);
+                Op::Pick
+            }
+            StmtKind::Print(expr) => {
+                self.expr(expr);
+                Op::Print
+            }
+            StmtKind::Read(var) => Op::Read(var.slot()),
+            StmtKind::ReadInt(var) => Op::ReadInt(var.slot()),
+            StmtKind::WriteFile(path, text) | StmtKind::AppendFile(path, text) => {
+                self.expr(path);
+                self.emit(Op::Path, span);
+                self.expr(text);
+                Op::WriteFile(matches!(stmt.kind, StmtKind::AppendFile(..)))
+            }
+            StmtKind::CommentOp(SpanLit(start, end)) => {
+                let start = self.address(*start);
+                self.address(*end);
+                Op::CommentOut(start)
+            }
+            StmtKind::Uncomment(var) => Op::Uncomment(self.var(var)),
+            StmtKind::Copy(var, address) => Op::Copy(self.var(var), self.address(*address)),
+            StmtKind::Move(var, address) => Op::Move(self.var(var), self.address(*address)),
+            StmtKind::Call(var) => Op::Call(self.var(var)),
+            StmtKind::Include(path, module) => {
+                let path = self.name(path);
+                Op::Include(path, module.as_ref().map(Var::slot))
+            }
+            StmtKind::Conditional(guard, stmt) => {
+                match guard {
+                    Some(guard) => self.expr(guard),
+                    None => {
+                        self.emit(Op::PopStack, span);
+                    }
+                }
+                let jump = self.emit(Op::JumpUnless(0), span);
+                self.stmt(stmt);
+                self.patch(jump);
+                return;
+            }
+        };
+        self.emit(op, span);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let span = expr.span;
+        let op = match &expr.kind {
+            ExprKind::Literal(lit) => Op::Const(self.constant(Interpreter::visit_literal(lit))),
+            ExprKind::Comment(comment) => {
+                Op::Const(self.constant(Value::Comment(Rc::clone(comment))))
+            }
+            ExprKind::Ident(var) => Op::Load(var.slot()),
+            ExprKind::Pop => Op::PopStack,
+            ExprKind::BinaryOp(op @ (TokenKind::And | TokenKind::Or), lhs, rhs) => {
+                // `and` stops at the first falsy operand, `or` at the first truthy one
+                self.expr(lhs);
+                let jump = self.emit(Op::ShortCircuit(*op == TokenKind::Or, 0), span);
+                self.expr(rhs);
+                self.emit(Op::Truthy, span);
+                self.patch(jump);
+                return;
+            }
+            ExprKind::BinaryOp(op, lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+                Op::Binary(*op)
+            }
+            ExprKind::UnaryOp(op, rhs) => {
+                self.expr(rhs);
+                Op::Unary(*op)
+            }
+            ExprKind::Call(name, args) => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                Op::Builtin(self.name(name), index(args.len()))
+            }
+            ExprKind::Depth => Op::Depth,
+            ExprKind::Peek => Op::Peek,
+            ExprKind::StackIndex(offset) => {
+                self.expr(offset);
+                self.emit(Op::Offset(Offset::StackIndex), offset.span);
+                Op::StackIndex
+            }
+            ExprKind::List(items) => {
+                for item in items {
+                    self.expr(item);
+                }
+                Op::List(index(items.len()))
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                    self.emit(Op::MapKey, key.span);
+                }
+                Op::Map(index(entries.len()))
+            }
+            ExprKind::Index(expr, index) => {
+                self.expr(expr);
+                self.expr(index);
+                Op::Index
+            }
+            ExprKind::Slice(expr, from, to) => {
+                self.expr(expr);
+                for bound in from.iter().chain(to) {
+                    self.expr(bound);
+                }
+                Op::Slice(from.is_some(), to.is_some())
+            }
+        };
+        self.emit(op, span);
+    }
+}
+
+fn index(n: usize) -> Index {
+    Index::try_from(n).expect("a single line can't have more than u32::MAX parts")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chunk, Offset, Op};
+    use crate::{
+        interpreter::value::Value,
+        lexer::TokenKind,
+        parser::Parser,
+        resolver::{Interner, Resolver, Slot},
+    };
+
+    fn compile(input: &str) -> Chunk {
+        let mut stmt = Parser::new(input).parse_stmt().unwrap();
+        Resolver::new(&mut Interner::default()).resolve(&mut stmt);
+        Chunk::compile(&stmt)
+    }
+
+    #[test]
+    fn test_compile_expression() {
+        let chunk = compile("print x + 2 * pop");
+        assert_eq!(
+            chunk.code,
+            vec![
+                Op::Load(Slot(0)),
+                Op::Const(0),
+                Op::PopStack,
+                Op::Binary(TokenKind::Multiply),
+                Op::Binary(TokenKind::Plus),
+                Op::Print,
+            ]
+        );
+        assert_eq!(chunk.constants, vec![Value::Int(2)]);
+        assert!(chunk.names.is_empty());
+        assert_eq!(chunk.spans.len(), chunk.code.len());
+    }
+
+    #[test]
+    fn test_compile_jumps() {
+        let chunk = compile("a or b ? call f");
+        assert_eq!(
+            chunk.code,
+            vec![
+                Op::Load(Slot(0)),
+                Op::ShortCircuit(true, 4),
+                Op::Load(Slot(1)),
+                Op::Truthy,
+                Op::JumpUnless(6),
+                Op::Call(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_stack_offset() {
+        let chunk = compile("pick stack[0]");
+        assert_eq!(
+            chunk.code,
+            vec![
+                Op::Const(0),
+                Op::Offset(Offset::StackIndex),
+                Op::StackIndex,
+                Op::Offset(Offset::Pick),
+                Op::Pick,
+            ]
+        );
+        assert_eq!(chunk.spans[1], chunk.spans[0]);
+    }
+}
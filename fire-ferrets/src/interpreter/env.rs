@@ -1,39 +1,283 @@
-use std::collections::HashMap;
-
 use super::value::Value;
 use super::ValueResult;
+use crate::error::{FerError, FerResult};
+use crate::lexer::Span;
+use crate::resolver::{Interner, Slot};
 
+#[derive(Default)]
 pub struct Env {
-    store: HashMap<String, Value>,
+    /// The slots of the variables, values are indexed by them
+    names: Interner,
+    /// `None` for variables that have a slot but aren't defined
+    values: Vec<Option<Value>>,
     stack: Vec<Value>,
 }
 
 impl Env {
     pub fn new() -> Self {
-        Self {
-            stack: vec![],
-            store: HashMap::new(),
+        Self::default()
+    }
+
+    /// The names of the variables in this environment, for resolving them
+    pub fn names(&mut self) -> &mut Interner {
+        &mut self.names
+    }
+
+    pub fn set(&mut self, slot: Slot, value: Value) {
+        let index = slot.0 as usize;
+        if index >= self.values.len() {
+            self.values.resize(index + 1, None);
+        }
+        self.values[index] = Some(value);
+    }
+
+    /// Get the value of the variable in `slot`, `span` is where it's being used
+    pub fn get(&self, slot: Slot, span: Span) -> ValueResult {
+        match self.values.get(slot.0 as usize) {
+            Some(Some(value)) => Ok(value.clone()),
+            _ => Err(self.undefined(slot, span)),
         }
     }
 
-    pub fn set(&mut self, name: String, value: Value) {
-        self.store.insert(name, value);
+    /// Get the variable in `slot` to modify it in place
+    pub fn get_mut(&mut self, slot: Slot, span: Span) -> FerResult<&mut Value> {
+        let index = slot.0 as usize;
+        if !matches!(self.values.get(index), Some(Some(_))) {
+            return Err(self.undefined(slot, span));
+        }
+        // just checked that it's there
+        Ok(self.values[index].as_mut().unwrap())
     }
 
-    pub fn get(&self, name: &str) -> ValueResult {
-        self.store
-            .get(name)
-            .map(Clone::clone)
-            .ok_or(format!("{} is undefined", name))
+    pub fn remove(&mut self, slot: Slot, span: Span) -> ValueResult {
+        self.values
+            .get_mut(slot.0 as usize)
+            .and_then(Option::take)
+            .ok_or_else(|| self.undefined(slot, span))
     }
 
     pub fn push(&mut self, value: Value) {
         self.stack.push(value);
     }
 
-    pub fn pop(&mut self) -> ValueResult {
+    /// The stack, bottom first
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    /// Every variable and its value, sorted by name
+    pub fn vars(&self) -> Vec<(&str, &Value)> {
+        let mut vars: Vec<_> = self
+            .values
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, value)| {
+                let name = self.names.name(Slot(slot as u32));
+                value.as_ref().map(|value| (name, value))
+            })
+            .collect();
+        vars.sort_by_key(|(name, _)| *name);
+        vars
+    }
+
+    pub fn pop(&mut self, span: Span) -> ValueResult {
         self.stack
             .pop()
-            .ok_or_else(|| "The stack is empty".to_string())
+            .ok_or_else(|| FerError::Runtime(span, "The stack is empty".to_string()))
+    }
+
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+
+    /// The value `n` places below the top of the stack, without removing it
+    pub fn peek(&self, n: usize, span: Span) -> ValueResult {
+        self.stack
+            .len()
+            .checked_sub(n + 1)
+            .map(|index| self.stack[index].clone())
+            .ok_or_else(|| match self.stack.len() {
+                0 => FerError::Runtime(span, "The stack is empty".to_string()),
+                len => FerError::Runtime(
+                    span,
+                    format!(
+                        "stack[{}] is out of bounds, the stack has {} value(s)",
+                        n, len
+                    ),
+                ),
+            })
+    }
+
+    /// Copy the value `n` places below the top of the stack onto the top, `pick 0` is `dup`
+    pub fn pick(&mut self, n: usize, span: Span) -> FerResult<()> {
+        let op = format!("pick {}", n);
+        self.require(&op, n + 1, span)?;
+        let value = self.stack[self.stack.len() - 1 - n].clone();
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// ( a -- a a )
+    pub fn dup(&mut self, span: Span) -> FerResult<()> {
+        self.pick(0, span)
+            .map_err(|_| self.underflow("dup", 1, span))
+    }
+
+    /// ( a b -- a b a )
+    pub fn over(&mut self, span: Span) -> FerResult<()> {
+        self.pick(1, span)
+            .map_err(|_| self.underflow("over", 2, span))
+    }
+
+    /// ( a b -- b a )
+    pub fn swap(&mut self, span: Span) -> FerResult<()> {
+        self.require("swap", 2, span)?;
+        let len = self.stack.len();
+        self.stack.swap(len - 1, len - 2);
+        Ok(())
+    }
+
+    /// ( a b c -- b c a )
+    pub fn rot(&mut self, span: Span) -> FerResult<()> {
+        self.require("rot", 3, span)?;
+        let len = self.stack.len();
+        self.stack[len - 3..].rotate_left(1);
+        Ok(())
+    }
+
+    /// Check that there are at least `n` values on the stack for `op`
+    fn require(&self, op: &str, n: usize, span: Span) -> FerResult<()> {
+        if self.stack.len() < n {
+            Err(self.underflow(op, n, span))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn undefined(&self, slot: Slot, span: Span) -> FerError {
+        FerError::Runtime(span, format!("{} is undefined", self.names.name(slot)))
+    }
+
+    fn underflow(&self, op: &str, n: usize, span: Span) -> FerError {
+        FerError::Runtime(
+            span,
+            format!(
+                "Stack underflow: {} needs {} value(s), but the stack has {}",
+                op,
+                n,
+                self.stack.len()
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Env;
+    use crate::{error::FerError, interpreter::value::Value, lexer::Span};
+
+    const SPAN: Span = Span { start: 0, end: 0 };
+
+    fn env(values: &[i64]) -> Env {
+        let mut env = Env::new();
+        for &n in values {
+            env.push(Value::Int(n));
+        }
+        env
+    }
+
+    fn ints(env: &Env) -> Vec<i64> {
+        env.stack()
+            .iter()
+            .map(|value| match value {
+                Value::Int(n) => *n,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stack_ops() {
+        let mut stack = env(&[1, 2, 3]);
+        stack.rot(SPAN).unwrap();
+        assert_eq!(ints(&stack), vec![2, 3, 1]);
+        stack.swap(SPAN).unwrap();
+        assert_eq!(ints(&stack), vec![2, 1, 3]);
+        stack.over(SPAN).unwrap();
+        assert_eq!(ints(&stack), vec![2, 1, 3, 1]);
+        stack.dup(SPAN).unwrap();
+        assert_eq!(ints(&stack), vec![2, 1, 3, 1, 1]);
+        stack.pick(4, SPAN).unwrap();
+        assert_eq!(ints(&stack), vec![2, 1, 3, 1, 1, 2]);
+        assert_eq!(stack.depth(), 6);
+        stack.clear();
+        assert_eq!(stack.depth(), 0);
+    }
+
+    #[test]
+    fn test_peek() {
+        let stack = env(&[1, 2, 3]);
+        assert_eq!(stack.peek(0, SPAN), Ok(Value::Int(3)));
+        assert_eq!(stack.peek(2, SPAN), Ok(Value::Int(1)));
+        assert_eq!(stack.depth(), 3);
+        assert_eq!(
+            stack.peek(3, SPAN),
+            Err(FerError::Runtime(
+                SPAN,
+                "stack[3] is out of bounds, the stack has 3 value(s)".to_string()
+            ))
+        );
+        assert!(env(&[]).peek(0, SPAN).is_err());
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        let underflow = |msg: &str| Err(FerError::Runtime(SPAN, msg.to_string()));
+        assert_eq!(
+            env(&[]).dup(SPAN),
+            underflow("Stack underflow: dup needs 1 value(s), but the stack has 0")
+        );
+        assert_eq!(
+            env(&[1]).swap(SPAN),
+            underflow("Stack underflow: swap needs 2 value(s), but the stack has 1")
+        );
+        assert_eq!(
+            env(&[1]).over(SPAN),
+            underflow("Stack underflow: over needs 2 value(s), but the stack has 1")
+        );
+        assert_eq!(
+            env(&[1, 2]).rot(SPAN),
+            underflow("Stack underflow: rot needs 3 value(s), but the stack has 2")
+        );
+        assert_eq!(
+            env(&[1, 2]).pick(2, SPAN),
+            underflow("Stack underflow: pick 2 needs 3 value(s), but the stack has 2")
+        );
+        // nothing changes when an operation fails
+        let mut stack = env(&[1, 2]);
+        assert!(stack.rot(SPAN).is_err());
+        assert_eq!(ints(&stack), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_vars() {
+        let mut env = Env::new();
+        let b = env.names().intern("b");
+        let a = env.names().intern("a");
+        env.set(b, Value::Int(2));
+        env.set(a, Value::Int(1));
+        assert_eq!(env.get(a, SPAN), Ok(Value::Int(1)));
+        let vars: Vec<_> = env.vars().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(vars, vec!["a", "b"]);
+        assert_eq!(env.remove(b, SPAN), Ok(Value::Int(2)));
+        assert_eq!(
+            env.get(b, SPAN),
+            Err(FerError::Runtime(SPAN, "b is undefined".to_string()))
+        );
+        assert!(env.get_mut(b, SPAN).is_err());
     }
 }
@@ -0,0 +1,74 @@
+use std::{fs, io::Write, path::Path};
+
+use super::{value::Value, Interpreter, ValueResult, Visitor};
+use crate::{
+    error::{FerError, FerResult},
+    lexer::Span,
+    parser::Expr,
+};
+
+type StmtResult = FerResult<()>;
+
+impl Interpreter {
+    /// Check that `path` is a string and that the filesystem may be used at all
+    pub(super) fn path(&self, path: Value, span: Span) -> FerResult<String> {
+        if !self.fs_access {
+            return Err(FerError::Runtime(
+                span,
+                "Filesystem access is disabled".to_string(),
+            ));
+        }
+        match path {
+            Value::Str(path) => Ok(path.to_string()),
+            value => Err(FerError::Runtime(
+                span,
+                format!("File paths must be strings, got {}", value.type_name()),
+            )),
+        }
+    }
+
+    /// The contents of the file at `path`
+    pub(super) fn read_file(&self, path: Value, span: Span) -> ValueResult {
+        let path = self.path(path, span)?;
+        fs::read_to_string(&path)
+            .map(|text| Value::Str(text.into()))
+            .map_err(|e| io_error("read", &path, e, span))
+    }
+
+    /// Whether there's a file at `path`, directories don't count
+    pub(super) fn file_exists(&self, path: Value, span: Span) -> ValueResult {
+        let path = self.path(path, span)?;
+        Ok(Value::Bool(Path::new(&path).is_file()))
+    }
+
+    pub(super) fn visit_write_file(
+        &mut self,
+        path: &Expr,
+        text: &Expr,
+        append: bool,
+        span: Span,
+    ) -> StmtResult {
+        let path = self.visit_expr(path)?;
+        let path = self.path(path, span)?;
+        let text = self.visit_expr(text)?;
+        write_file(&path, text, append, span)
+    }
+}
+
+/// Write `text` to the file at `path`, after its current contents if `append` is set
+pub(super) fn write_file(path: &str, text: Value, append: bool, span: Span) -> StmtResult {
+    let text = text.to_string();
+    let action = if append { "append to" } else { "write" };
+    fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|e| io_error(action, path, e, span))
+}
+
+pub(super) fn io_error(action: &str, path: &str, e: std::io::Error, span: Span) -> FerError {
+    FerError::Runtime(span, format!("Couldn't {} {}: {}", action, path, e))
+}
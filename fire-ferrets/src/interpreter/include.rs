@@ -0,0 +1,105 @@
+use std::{collections::BTreeMap, fs, mem, path::Path, rc::Rc};
+
+use super::{
+    env::Env,
+    files::io_error,
+    program::{Line, Program},
+    value::Value,
+    Interpreter,
+};
+use crate::{
+    error::{FerError, FerResult, SourceFile},
+    lexer::Span,
+    parser::Parser,
+    resolver::Slot,
+};
+
+type StmtResult = FerResult<()>;
+
+impl Interpreter {
+    /// Run the file at `path`, relative to the file doing the including.
+    /// With a module name, the file gets an environment of its own,
+    /// and its comments are put into a map in the `module` slot afterwards.
+    pub(super) fn visit_include(
+        &mut self,
+        path: &str,
+        module: Option<Slot>,
+        span: Span,
+    ) -> StmtResult {
+        let path = self.path(Value::Str(path.into()), span)?;
+        let dir = self
+            .files
+            .last()
+            .and_then(|file| file.parent())
+            .unwrap_or_else(|| Path::new(""));
+        let path = dir.join(path);
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| io_error("include", &path.display().to_string(), e, span))?;
+        self.check_cycle(&path, span)?;
+
+        let file = Rc::new(SourceFile {
+            name: path.display().to_string(),
+            contents,
+        });
+        let (lines, errors) = Parser::new(&file.contents).parse_all();
+        if !errors.is_empty() {
+            return Err(FerError::Included(span, file, errors));
+        }
+        let mut lines: Vec<_> = lines
+            .into_iter()
+            .map(|(stmt, source)| Line::new(stmt, source))
+            .collect();
+
+        let caller_env = module.map(|_| mem::replace(&mut self.env, Env::new()));
+        // there's nowhere to show warnings while running, guarded uses fail when they're reached
+        let undefined = self.resolve(&mut lines).errors;
+        let result = if undefined.is_empty() {
+            self.files.push(path);
+            let base = self.call_stack.len();
+            self.call_stack.push(Program::new(lines));
+            let result = self.run_frames(base);
+            self.files.pop();
+            result.map_err(|e| vec![e])
+        } else {
+            Err(undefined)
+        };
+
+        if let Some(caller_env) = caller_env {
+            let module_env = mem::replace(&mut self.env, caller_env);
+            if result.is_ok() {
+                let comments: BTreeMap<_, _> = module_env
+                    .vars()
+                    .into_iter()
+                    .filter(|(_, value)| matches!(value, Value::Comment(_)))
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect();
+                // `module` is always `Some` if there's a caller environment to restore
+                self.env.set(module.unwrap(), Value::Map(Rc::new(comments)));
+            }
+        }
+        result.map_err(|errors| FerError::Included(span, file, errors))
+    }
+
+    /// Make sure `path` isn't already being included, which would never end
+    fn check_cycle(&self, path: &Path, span: Span) -> StmtResult {
+        let canonical = |path: &Path| fs::canonicalize(path).ok();
+        let target = canonical(path);
+        let start = match self
+            .files
+            .iter()
+            .position(|file| target.is_some() && canonical(file) == target)
+        {
+            Some(start) => start,
+            None => return Ok(()),
+        };
+        let chain: Vec<_> = self.files[start..]
+            .iter()
+            .chain(Some(&path.to_path_buf()))
+            .map(|file| file.display().to_string())
+            .collect();
+        Err(FerError::Runtime(
+            span,
+            format!("Circular include: {}", chain.join(" -> ")),
+        ))
+    }
+}
@@ -0,0 +1,237 @@
+use std::rc::Rc;
+
+use super::{value::Value, ValueResult};
+use crate::{
+    error::{FerError, FerResult},
+    lexer::Span,
+};
+
+/// Get the element of a list, the character of a string or the value in a map at `index`
+pub fn index(value: &Value, index: &Value, span: Span) -> ValueResult {
+    match value {
+        Value::List(items) => Ok(items[position(index, items.len(), span)?].clone()),
+        Value::Map(entries) => entries
+            .get(key(index, span)?)
+            .cloned()
+            .ok_or_else(|| missing_key(index, span)),
+        Value::Str(s) => {
+            let chars: Vec<_> = s.chars().collect();
+            let c = chars[position(index, chars.len(), span)?];
+            Ok(Value::Str(c.to_string().into()))
+        }
+        _ => Err(cannot_index(value, span)),
+    }
+}
+
+/// Get the elements of a list, or the characters of a string, from `from` up to but not including `to`
+pub fn slice(value: &Value, from: Option<Value>, to: Option<Value>, span: Span) -> ValueResult {
+    let len = match value {
+        Value::List(items) => items.len(),
+        Value::Str(s) => s.chars().count(),
+        _ => {
+            return Err(FerError::Runtime(
+                span,
+                format!("Cannot slice a {}", value.type_name()),
+            ))
+        }
+    };
+    let from = match from {
+        Some(from) => bound(&from, len, span)?,
+        None => 0,
+    };
+    let to = match to {
+        Some(to) => bound(&to, len, span)?,
+        None => len,
+    };
+    if from > to {
+        return Err(FerError::Runtime(
+            span,
+            format!("Slice starts at {} but ends at {}", from, to),
+        ));
+    }
+
+    Ok(match value {
+        Value::List(items) => Value::List(Rc::new(items[from..to].to_vec())),
+        Value::Str(s) => {
+            let s: String = s.chars().skip(from).take(to - from).collect();
+            Value::Str(s.into())
+        }
+        _ => unreachable!(),
+    })
+}
+
+/// Get a mutable reference to the element of a list or map at `index`, to assign to part of it
+pub fn index_mut<'a>(value: &'a mut Value, index: &Value, span: Span) -> FerResult<&'a mut Value> {
+    match value {
+        Value::List(items) => {
+            let index = position(index, items.len(), span)?;
+            Ok(&mut Rc::make_mut(items)[index])
+        }
+        Value::Map(entries) => Rc::make_mut(entries)
+            .get_mut(key(index, span)?)
+            .ok_or_else(|| missing_key(index, span)),
+        _ => Err(FerError::Runtime(
+            span,
+            format!("Cannot assign to an element of a {}", value.type_name()),
+        )),
+    }
+}
+
+/// Replace the element of a list at `index`, or insert `index` into a map
+pub fn assign(target: &mut Value, index: &Value, value: Value, span: Span) -> FerResult<()> {
+    match target {
+        Value::Map(entries) => {
+            let key = key(index, span)?.to_string();
+            Rc::make_mut(entries).insert(key, value);
+        }
+        _ => *index_mut(target, index, span)? = value,
+    }
+    Ok(())
+}
+
+/// Remove the element of a list or the entry of a map at `index`, returning it
+pub fn remove(value: &mut Value, index: &Value, span: Span) -> ValueResult {
+    match value {
+        Value::List(items) => {
+            let position = position(index, items.len(), span)?;
+            Ok(Rc::make_mut(items).remove(position))
+        }
+        Value::Map(entries) => Rc::make_mut(entries)
+            .remove(key(index, span)?)
+            .ok_or_else(|| missing_key(index, span)),
+        _ => Err(FerError::Runtime(
+            span,
+            format!("Cannot remove an element from a {}", value.type_name()),
+        )),
+    }
+}
+
+/// Maps are keyed by strings only
+pub fn key(index: &Value, span: Span) -> FerResult<&str> {
+    match index {
+        Value::Str(key) => Ok(key),
+        _ => Err(FerError::Runtime(
+            span,
+            format!("Map keys must be strings, got {}", index.type_name()),
+        )),
+    }
+}
+
+fn missing_key(index: &Value, span: Span) -> FerError {
+    FerError::Runtime(span, format!("The map has no key {}", index.repr()))
+}
+
+/// Turn a possibly negative `index` into a position in a sequence of length `len`,
+/// negative indices count from the end
+fn position(index: &Value, len: usize, span: Span) -> FerResult<usize> {
+    match resolve(index, len, span)? {
+        Some(position) if position < len => Ok(position),
+        _ => Err(FerError::Runtime(
+            span,
+            format!("Index {} is out of bounds for length {}", index, len),
+        )),
+    }
+}
+
+/// Like `position`, but a slice bound may also point just past the end
+fn bound(index: &Value, len: usize, span: Span) -> FerResult<usize> {
+    match resolve(index, len, span)? {
+        Some(position) if position <= len => Ok(position),
+        _ => Err(FerError::Runtime(
+            span,
+            format!("Slice bound {} is out of bounds for length {}", index, len),
+        )),
+    }
+}
+
+/// Count negative indices from the end, `None` if that goes past the start
+fn resolve(index: &Value, len: usize, span: Span) -> FerResult<Option<usize>> {
+    match index {
+        Value::Int(n) if *n >= 0 => Ok(Some(*n as usize)),
+        Value::Int(n) => Ok(len.checked_sub(n.unsigned_abs() as usize)),
+        _ => Err(FerError::Runtime(
+            span,
+            format!("Indices must be integers, got {}", index.type_name()),
+        )),
+    }
+}
+
+fn cannot_index(value: &Value, span: Span) -> FerError {
+    FerError::Runtime(span, format!("Cannot index into a {}", value.type_name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{assign, index, index_mut, remove, slice};
+    use crate::{interpreter::value::Value, lexer::Span};
+
+    const SPAN: Span = Span { start: 0, end: 0 };
+
+    fn list(items: &[i64]) -> Value {
+        Value::List(Rc::new(items.iter().map(|&n| Value::Int(n)).collect()))
+    }
+
+    #[test]
+    fn test_index() {
+        let xs = list(&[1, 2, 3]);
+        assert_eq!(index(&xs, &Value::Int(0), SPAN), Ok(Value::Int(1)));
+        assert_eq!(index(&xs, &Value::Int(-1), SPAN), Ok(Value::Int(3)));
+        assert!(index(&xs, &Value::Int(3), SPAN).is_err());
+        assert!(index(&xs, &Value::Int(-4), SPAN).is_err());
+        assert!(index(&xs, &Value::Str("0".into()), SPAN).is_err());
+        assert!(index(&Value::Int(1), &Value::Int(0), SPAN).is_err());
+        assert_eq!(
+            index(&Value::Str("héllo".into()), &Value::Int(1), SPAN),
+            Ok(Value::Str("é".into()))
+        );
+    }
+
+    #[test]
+    fn test_slice() {
+        let xs = list(&[1, 2, 3, 4]);
+        let int = |n| Some(Value::Int(n));
+        assert_eq!(slice(&xs, int(1), int(3), SPAN), Ok(list(&[2, 3])));
+        assert_eq!(slice(&xs, None, int(-1), SPAN), Ok(list(&[1, 2, 3])));
+        assert_eq!(slice(&xs, int(4), None, SPAN), Ok(list(&[])));
+        assert!(slice(&xs, int(3), int(1), SPAN).is_err());
+        assert!(slice(&xs, None, int(5), SPAN).is_err());
+        assert_eq!(
+            slice(&Value::Str("hello".into()), int(1), int(-1), SPAN),
+            Ok(Value::Str("ell".into()))
+        );
+    }
+
+    #[test]
+    fn test_maps() {
+        let key = |key: &str| Value::Str(key.into());
+        let mut map = Value::Map(Default::default());
+        assign(&mut map, &key("a"), Value::Int(1), SPAN).unwrap();
+        assign(&mut map, &key("b"), Value::Int(2), SPAN).unwrap();
+        assign(&mut map, &key("a"), Value::Int(3), SPAN).unwrap();
+        assert_eq!(index(&map, &key("a"), SPAN), Ok(Value::Int(3)));
+        assert_eq!(map.to_string(), r#"["a": 3, "b": 2]"#);
+        assert!(index(&map, &key("c"), SPAN).is_err());
+        assert!(index(&map, &Value::Int(0), SPAN).is_err());
+        assert!(index_mut(&mut map, &key("c"), SPAN).is_err());
+        assert_eq!(remove(&mut map, &key("b"), SPAN), Ok(Value::Int(2)));
+        assert!(remove(&mut map, &key("b"), SPAN).is_err());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut xs = list(&[1, 2, 3]);
+        assert_eq!(remove(&mut xs, &Value::Int(-2), SPAN), Ok(Value::Int(2)));
+        assert_eq!(xs, list(&[1, 3]));
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let xs = list(&[1, 2]);
+        let mut ys = xs.clone();
+        assign(&mut ys, &Value::Int(0), Value::Int(3), SPAN).unwrap();
+        assert_eq!(xs, list(&[1, 2]));
+        assert_eq!(ys, list(&[3, 2]));
+    }
+}
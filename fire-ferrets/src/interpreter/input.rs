@@ -0,0 +1,56 @@
+use std::io::{self, BufRead};
+
+/// Where `read` statements get their input from
+pub enum Input {
+    /// Locked for each read, so that the REPL can share stdin with the program
+    Stdin,
+    Reader(Box<dyn BufRead>),
+}
+
+impl Input {
+    /// Read a line without its line ending, `None` at the end of input
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        let read = match self {
+            Input::Stdin => io::stdin().read_line(&mut line)?,
+            Input::Reader(reader) => reader.read_line(&mut line)?,
+        };
+        if read == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    /// Check whether there's nothing left to read, waiting for input if necessary
+    pub fn at_eof(&mut self) -> io::Result<bool> {
+        Ok(match self {
+            Input::Stdin => io::stdin().lock().fill_buf()?.is_empty(),
+            Input::Reader(reader) => reader.fill_buf()?.is_empty(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::Input;
+
+    #[test]
+    fn test_read_lines() {
+        let mut input = Input::Reader(Box::new(Cursor::new("one\r\ntwo\n\nthree")));
+        assert_eq!(input.read_line().unwrap(), Some("one".to_string()));
+        assert_eq!(input.read_line().unwrap(), Some("two".to_string()));
+        assert_eq!(input.read_line().unwrap(), Some("".to_string()));
+        assert!(!input.at_eof().unwrap());
+        assert_eq!(input.read_line().unwrap(), Some("three".to_string()));
+        assert!(input.at_eof().unwrap());
+        assert_eq!(input.read_line().unwrap(), None);
+    }
+}
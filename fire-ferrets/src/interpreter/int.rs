@@ -0,0 +1,183 @@
+use std::{convert::TryFrom, str::FromStr};
+
+use num_bigint::BigInt;
+
+use super::{value::Value, ValueResult};
+use crate::{error::FerError, lexer::Span, lexer::TokenKind};
+
+/// What integer arithmetic does when the result doesn't fit in an `i64`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Overflow {
+    /// Raise a runtime error
+    #[default]
+    Checked,
+    /// Wrap around at the boundary
+    Wrapping,
+    /// Clamp to `i64::MIN` or `i64::MAX`
+    Saturating,
+    /// Switch to arbitrary precision integers
+    BigInt,
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checked" => Ok(Self::Checked),
+            "wrapping" => Ok(Self::Wrapping),
+            "saturating" => Ok(Self::Saturating),
+            "bigint" => Ok(Self::BigInt),
+            _ => Err(format!(
+                "Unknown overflow mode '{}', expected checked, wrapping, saturating or bigint",
+                s
+            )),
+        }
+    }
+}
+
+impl Overflow {
+    /// Apply the arithmetic operator `op` to two integers
+    pub fn arith(self, op: &TokenKind, lhs: i64, rhs: i64, span: Span) -> ValueResult {
+        if *op == TokenKind::Divide && rhs == 0 {
+            return Err(division_by_zero(span));
+        }
+        let checked = match op {
+            TokenKind::Plus => lhs.checked_add(rhs),
+            TokenKind::Minus => lhs.checked_sub(rhs),
+            TokenKind::Multiply => lhs.checked_mul(rhs),
+            TokenKind::Divide => lhs.checked_div(rhs),
+            _ => unreachable!(),
+        };
+        if let Some(n) = checked {
+            return Ok(Value::Int(n));
+        }
+
+        Ok(match self {
+            Self::Checked => return Err(overflow(op, span)),
+            Self::Wrapping => Value::Int(match op {
+                TokenKind::Plus => lhs.wrapping_add(rhs),
+                TokenKind::Minus => lhs.wrapping_sub(rhs),
+                TokenKind::Multiply => lhs.wrapping_mul(rhs),
+                _ => lhs.wrapping_div(rhs),
+            }),
+            Self::Saturating => Value::Int(match op {
+                TokenKind::Plus => lhs.saturating_add(rhs),
+                TokenKind::Minus => lhs.saturating_sub(rhs),
+                TokenKind::Multiply => lhs.saturating_mul(rhs),
+                _ => lhs.saturating_div(rhs),
+            }),
+            Self::BigInt => return big_arith(op, BigInt::from(lhs), BigInt::from(rhs), span),
+        })
+    }
+
+    /// Negate an integer
+    pub fn negate(self, n: i64, span: Span) -> ValueResult {
+        Ok(match n.checked_neg() {
+            Some(n) => Value::Int(n),
+            None => match self {
+                Self::Checked => return Err(overflow(&TokenKind::Minus, span)),
+                Self::Wrapping => Value::Int(n.wrapping_neg()),
+                Self::Saturating => Value::Int(n.saturating_neg()),
+                Self::BigInt => normalize(-BigInt::from(n)),
+            },
+        })
+    }
+}
+
+/// Apply the arithmetic operator `op` to two arbitrary precision integers
+pub fn big_arith(op: &TokenKind, lhs: BigInt, rhs: BigInt, span: Span) -> ValueResult {
+    Ok(normalize(match op {
+        TokenKind::Plus => lhs + rhs,
+        TokenKind::Minus => lhs - rhs,
+        TokenKind::Multiply => lhs * rhs,
+        TokenKind::Divide if rhs == BigInt::from(0) => return Err(division_by_zero(span)),
+        TokenKind::Divide => lhs / rhs,
+        _ => unreachable!(),
+    }))
+}
+
+/// `Value::BigInt` is only used for integers that don't fit in an `i64`
+pub fn normalize(n: BigInt) -> Value {
+    match i64::try_from(&n) {
+        Ok(n) => Value::Int(n),
+        Err(_) => Value::BigInt(n),
+    }
+}
+
+fn division_by_zero(span: Span) -> FerError {
+    FerError::Runtime(span, "Division by zero".to_string())
+}
+
+fn overflow(op: &TokenKind, span: Span) -> FerError {
+    FerError::Runtime(span, format!("Integer overflow in {}", op))
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+
+    use super::Overflow;
+    use crate::{
+        interpreter::value::Value,
+        lexer::{Span, TokenKind},
+    };
+
+    const SPAN: Span = Span { start: 0, end: 0 };
+
+    #[test]
+    fn test_overflow_modes() {
+        let add_max = |overflow: Overflow| overflow.arith(&TokenKind::Plus, i64::MAX, 1, SPAN);
+        assert!(add_max(Overflow::Checked).is_err());
+        assert_eq!(add_max(Overflow::Wrapping), Ok(Value::Int(i64::MIN)));
+        assert_eq!(add_max(Overflow::Saturating), Ok(Value::Int(i64::MAX)));
+        assert_eq!(
+            add_max(Overflow::BigInt),
+            Ok(Value::BigInt(BigInt::from(i64::MAX) + 1))
+        );
+    }
+
+    #[test]
+    fn test_division() {
+        for &overflow in &[
+            Overflow::Checked,
+            Overflow::Wrapping,
+            Overflow::Saturating,
+            Overflow::BigInt,
+        ] {
+            assert!(overflow.arith(&TokenKind::Divide, 1, 0, SPAN).is_err());
+            assert_eq!(
+                overflow.arith(&TokenKind::Divide, -7, 2, SPAN),
+                Ok(Value::Int(-3))
+            );
+        }
+        let min_div = |overflow: Overflow| overflow.arith(&TokenKind::Divide, i64::MIN, -1, SPAN);
+        assert!(min_div(Overflow::Checked).is_err());
+        assert_eq!(min_div(Overflow::Saturating), Ok(Value::Int(i64::MAX)));
+    }
+
+    #[test]
+    fn test_negate() {
+        assert!(Overflow::Checked.negate(i64::MIN, SPAN).is_err());
+        assert_eq!(Overflow::Checked.negate(5, SPAN), Ok(Value::Int(-5)));
+        assert_eq!(
+            Overflow::Wrapping.negate(i64::MIN, SPAN),
+            Ok(Value::Int(i64::MIN))
+        );
+    }
+
+    #[test]
+    fn test_big_results_shrink_back() {
+        let big = Overflow::BigInt
+            .arith(&TokenKind::Multiply, i64::MAX, 2, SPAN)
+            .unwrap();
+        if let Value::BigInt(big) = big {
+            assert_eq!(
+                super::big_arith(&TokenKind::Divide, big, BigInt::from(2), SPAN),
+                Ok(Value::Int(i64::MAX))
+            );
+        } else {
+            panic!("expected a big integer");
+        }
+    }
+}
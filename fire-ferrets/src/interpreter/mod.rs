@@ -1,126 +1,379 @@
+pub mod builtins;
+pub mod compiler;
 pub mod env;
+pub mod files;
+pub mod include;
+pub mod index;
+pub mod input;
+pub mod int;
+pub mod program;
 pub mod value;
+pub mod vm;
+
+use std::{collections::BTreeMap, convert::TryFrom, io::BufRead, path::PathBuf, rc::Rc};
+
+use num_bigint::BigInt;
 
 use env::*;
+use input::*;
+use int::*;
+use program::*;
 use value::*;
 
+use crate::error::{FerError, FerResult};
 use crate::lexer::*;
 use crate::parser::*;
+use crate::resolver::{Resolver, Slot, Undefined};
 
-type ValueResult = Result<Value, String>;
-type StmtResult = Result<(), String>;
+type ValueResult = FerResult<Value>;
+type StmtResult = FerResult<()>;
 
 pub trait Visitor {
     fn visit_expr(&mut self, expr: &Expr) -> ValueResult;
     fn visit_stmt(&mut self, stmt: &Stmt) -> StmtResult;
 }
 
+/// How deep `call`s can nest before we assume the program recurses infinitely
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// The longest string, in bytes, that repeating a string may create
+const MAX_REPEAT_LEN: usize = 1 << 30;
+
 pub struct Interpreter {
     env: Env,
+    /// The programs being run, innermost `call` last.
+    /// Comment operations are free to modify them.
+    call_stack: Vec<Program>,
+    /// What integer arithmetic does when it overflows
+    overflow: Overflow,
+    /// Where `read` and `readint` get their lines from
+    input: Input,
+    /// Whether scripts may read and write files
+    fs_access: bool,
+    /// The files being run, innermost `include` last, for resolving relative paths
+    files: Vec<PathBuf>,
+    /// Whether to compile lines to bytecode and run them on the VM instead of walking the tree
+    vm: bool,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Self { env: Env::new() }
+        Self::with_overflow(Overflow::default())
     }
 
-    pub fn run(&mut self, stmts: &[Stmt]) -> StmtResult {
-        for stmt in stmts {
-            self.visit_stmt(stmt)?;
+    pub fn with_overflow(overflow: Overflow) -> Self {
+        Self {
+            env: Env::new(),
+            call_stack: vec![],
+            overflow,
+            input: Input::Stdin,
+            fs_access: true,
+            files: vec![],
+            vm: false,
         }
-        Ok(())
     }
 
-    fn visit_literal(lit: &Lit) -> Value {
-        match lit {
-            Lit::Int(x) => Value::Int(*x),
-            Lit::Str(x) => Value::Str(x.to_string()),
-            Lit::Bool(x) => Value::Bool(*x),
+    /// Allow or forbid scripts from using the filesystem, for running untrusted code
+    pub fn set_fs_access(&mut self, allowed: bool) {
+        self.fs_access = allowed;
+    }
+
+    /// Run programs on the bytecode VM instead of the tree-walking interpreter
+    pub fn set_vm(&mut self, enabled: bool) {
+        self.vm = enabled;
+    }
+
+    /// Read input from `reader` instead of stdin
+    pub fn set_input(&mut self, reader: impl BufRead + 'static) {
+        self.input = Input::Reader(Box::new(reader));
+    }
+
+    /// Forget all variables and clear the stack, keeping the interpreter's options and input
+    pub fn reset(&mut self) {
+        self.env = Env::new();
+        self.call_stack = vec![];
+    }
+
+    /// Give the variables in `lines` slots before they're run,
+    /// and report the ones that are used but never defined
+    pub fn resolve(&mut self, lines: &mut [Line]) -> Undefined {
+        let defined = self
+            .env
+            .vars()
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        let stmts = lines.iter().map(|line| &*line.stmt);
+        let mut resolver = Resolver::checking(self.env.names(), stmts, defined);
+        for line in lines {
+            line.resolve(&mut resolver);
         }
+        resolver.finish()
+    }
+
+    pub fn run(&mut self, program: Program) -> StmtResult {
+        self.call_stack = vec![program];
+        self.run_frames(0)
     }
 
-    fn visit_binary_op(&mut self, op: &TokenKind, lhs: &Expr, rhs: &Expr) -> ValueResult {
-        let lhs = self.visit_expr(lhs).unwrap();
-        self.eval_infix_expression(op, lhs, rhs)
+    /// Run `program`, which was read from the file at `path`
+    pub fn run_file(&mut self, program: Program, path: &str) -> StmtResult {
+        self.files = vec![PathBuf::from(path)];
+        self.run(program)
     }
 
-    fn eval_infix_expression(&mut self, op: &TokenKind, lhs: Value, rhs: &Expr) -> ValueResult {
-        match lhs {
-            Value::Int(_) => {
-                if let TokenKind::And | TokenKind::Or = op {
-                    self.eval_infix_short_circuiting(op, lhs, rhs)
-                } else {
-                    self.eval_infix_integer(op, lhs, rhs)
+    /// Run the programs on the call stack until only `base` of them are left
+    fn run_frames(&mut self, base: usize) -> StmtResult {
+        while self.call_stack.len() > base {
+            let depth = self.call_stack.len();
+            match self.call_stack[depth - 1].current_mut() {
+                Some(line) => {
+                    if !line.commented {
+                        line.resolve(&mut Resolver::new(self.env.names()));
+                        if self.vm {
+                            let chunk = line.chunk();
+                            self.run_chunk(&chunk)?;
+                        } else {
+                            // cloned because executing a statement may modify the program
+                            let stmt = Rc::clone(&line.stmt);
+                            self.visit_stmt(&stmt)?;
+                        }
+                    }
+                    // a `call` may have pushed a new program, but it's the caller that moves on
+                    self.call_stack[depth - 1].advance();
+                }
+                None => {
+                    self.call_stack.pop();
                 }
             }
-            Value::Str(_) => todo!(),
-            Value::Comment(_) => todo!(),
-            Value::Bool(_) => self.eval_infix_short_circuiting(op, lhs, rhs),
         }
+        Ok(())
     }
 
-    fn eval_infix_integer(&mut self, op: &TokenKind, lhs: Value, rhs: &Expr) -> ValueResult {
-        let rhs = self.visit_expr(rhs).unwrap();
-        Ok(match op {
-            TokenKind::Plus => Value::Int(lhs.to_int()? + rhs.to_int()?),
-            TokenKind::Minus => Value::Int(lhs.to_int()? - rhs.to_int()?),
-            TokenKind::Multiply => Value::Int(lhs.to_int()? * rhs.to_int()?),
-            TokenKind::Divide => Value::Int(lhs.to_int()? / rhs.to_int()?),
-            TokenKind::Equals => Value::Bool(lhs.to_int()? == rhs.to_int()?),
-            TokenKind::NotEq => Value::Bool(lhs.to_int()? != rhs.to_int()?),
-            TokenKind::Less => Value::Bool(lhs.to_int()? < rhs.to_int()?),
-            TokenKind::LessEq => Value::Bool(lhs.to_int()? <= rhs.to_int()?),
-            TokenKind::Greater => Value::Bool(lhs.to_int()? > rhs.to_int()?),
-            TokenKind::GreaterEq => Value::Bool(lhs.to_int()? >= rhs.to_int()?),
-            _ => unreachable!(),
-        })
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// The line that was being executed when `run` returned an error
+    pub fn current_line(&self) -> Option<&Line> {
+        self.call_stack.last().and_then(Program::current)
+    }
+
+    /// The innermost program being run
+    fn program(&mut self) -> &mut Program {
+        self.call_stack
+            .last_mut()
+            .expect("statements only run inside of a program")
     }
 
-    fn eval_infix_short_circuiting(
+    /// Get the comment in `var`, which may be a member of a module like `math.square`
+    fn get_comment(&self, var: &Var, span: Span) -> FerResult<Rc<CommentValue>> {
+        let mut value = self.env.get(var.slot(), span)?;
+        for member in var.name.split('.').skip(1) {
+            value = index::index(&value, &Value::Str(member.into()), span)?;
+        }
+        match value {
+            Value::Comment(comment) => Ok(comment),
+            _ => Err(FerError::Runtime(span, format!("{} is not a comment", var))),
+        }
+    }
+
+    /// The lines of code in the comment in `var`, which are only parsed and resolved the first time
+    fn comment_lines(&mut self, var: &Var, span: Span) -> FerResult<Vec<Line>> {
+        let comment = self.get_comment(var, span)?;
+        let lines = comment
+            .lines(self.env.names())
+            .map_err(|e| FerError::Runtime(span, format!("{} is not valid code: {}", var, e)))?;
+        Ok(lines.to_vec())
+    }
+
+    /// Insert a comment literal as a new line at `address`.
+    /// The line pushes `comment` itself, so its code is never parsed again.
+    fn insert_comment(
+        &mut self,
+        comment: Rc<CommentValue>,
+        address: HalfSpanLit,
+        span: Span,
+    ) -> StmtResult {
+        let index = self.program().resolve_insert(address, span)?;
+        let source = format!("{{{}}}", comment.text());
+        let literal = Expr::new(ExprKind::Comment(comment), (0..source.len()).into());
+        let stmt = Stmt::new(StmtKind::Push(literal), (0..source.len()).into());
+        self.program()
+            .insert(index, vec![Line::generated(stmt, &source)]);
+        Ok(())
+    }
+
+    fn visit_literal(lit: &Lit) -> Value {
+        match lit {
+            Lit::Int(x) => Value::Int(*x),
+            Lit::Float(x) => Value::Float(*x),
+            Lit::Str(x) => Value::Str(x.as_str().into()),
+            Lit::Bool(x) => Value::Bool(*x),
+        }
+    }
+
+    fn visit_binary_op(
         &mut self,
         op: &TokenKind,
-        lhs: Value,
+        lhs: &Expr,
         rhs: &Expr,
+        span: Span,
     ) -> ValueResult {
-        Ok(match op {
-            TokenKind::And => Value::Bool(bool::from(lhs) && bool::from(self.visit_expr(rhs)?)),
-            TokenKind::Or => Value::Bool(bool::from(lhs) || bool::from(self.visit_expr(rhs)?)),
-            _ => unreachable!(),
-        })
+        let lhs = self.visit_expr(lhs)?;
+        match op {
+            TokenKind::And => Ok(Value::Bool(
+                bool::from(lhs) && bool::from(self.visit_expr(rhs)?),
+            )),
+            TokenKind::Or => Ok(Value::Bool(
+                bool::from(lhs) || bool::from(self.visit_expr(rhs)?),
+            )),
+            _ => {
+                let rhs = self.visit_expr(rhs)?;
+                self.eval_infix_expression(op, lhs, rhs, span)
+            }
+        }
     }
 
-    fn visit_ident(&mut self, name: &str) -> ValueResult {
-        if name == "pop" {
-            self.env.pop()
-        } else {
-            self.env.get(name)
+    /// Apply a binary operator other than the short circuiting `and` and `or`
+    fn eval_infix_expression(
+        &self,
+        op: &TokenKind,
+        lhs: Value,
+        rhs: Value,
+        span: Span,
+    ) -> ValueResult {
+        match op {
+            // every type can be compared for equality
+            TokenKind::Equals => Ok(Value::Bool(lhs == rhs)),
+            TokenKind::NotEq => Ok(Value::Bool(lhs != rhs)),
+            _ => match lhs {
+                Value::Int(_) | Value::BigInt(_) => self.eval_infix_integer(op, lhs, rhs, span),
+                Value::Float(_) => eval_infix_float(op, lhs, rhs, span),
+                Value::Str(_) => eval_infix_string(op, lhs, rhs, span),
+                Value::List(_) => match (op, lhs, rhs) {
+                    (TokenKind::Plus, Value::List(mut lhs), Value::List(rhs)) => {
+                        Rc::make_mut(&mut lhs).extend(rhs.iter().cloned());
+                        Ok(Value::List(lhs))
+                    }
+                    (_, lhs, rhs) => Err(type_error(op, &lhs, &rhs, span)),
+                },
+                Value::Comment(_) | Value::Bool(_) | Value::Map(_) => {
+                    Err(type_error(op, &lhs, &rhs, span))
+                }
+            },
         }
     }
 
-    fn visit_unary_op(&mut self, op: &TokenKind, rhs: &Expr) -> ValueResult {
-        let value = self.visit_expr(rhs).unwrap();
-        match value {
-            Value::Int(_) | Value::Bool(_) => Self::eval_prefix_op(op, value),
-            Value::Str(_) => todo!(),
-            Value::Comment(_) => todo!(),
+    fn eval_infix_integer(
+        &self,
+        op: &TokenKind,
+        lhs: Value,
+        rhs: Value,
+        span: Span,
+    ) -> ValueResult {
+        let (lhs, rhs) = match (lhs, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => {
+                return match op {
+                    TokenKind::Plus
+                    | TokenKind::Minus
+                    | TokenKind::Multiply
+                    | TokenKind::Divide => self.overflow.arith(op, lhs, rhs, span),
+                    _ => Ok(compare(op, &lhs, &rhs)),
+                }
+            }
+            (Value::Int(n), Value::Str(s)) if *op == TokenKind::Multiply => {
+                return repeat(&s, n, span)
+            }
+            (lhs, rhs @ Value::Float(_)) => return eval_infix_float(op, lhs, rhs, span),
+            // at least one of them doesn't fit in an `i64`
+            (Value::Int(lhs), Value::BigInt(rhs)) => (BigInt::from(lhs), rhs),
+            (Value::BigInt(lhs), Value::Int(rhs)) => (lhs, BigInt::from(rhs)),
+            (Value::BigInt(lhs), Value::BigInt(rhs)) => (lhs, rhs),
+            (lhs, rhs) => return Err(type_error(op, &lhs, &rhs, span)),
+        };
+        match op {
+            TokenKind::Plus | TokenKind::Minus | TokenKind::Multiply | TokenKind::Divide => {
+                big_arith(op, lhs, rhs, span)
+            }
+            _ => Ok(compare(op, &lhs, &rhs)),
         }
     }
 
-    fn eval_prefix_op(op: &TokenKind, rhs: Value) -> ValueResult {
-        Ok(match op {
-            TokenKind::Minus => Value::Int(-rhs.to_int()?),
-            TokenKind::Not => Value::Bool(!bool::from(rhs)),
-            _ => todo!(),
+    fn visit_unary_op(&mut self, op: &TokenKind, rhs: &Expr, span: Span) -> ValueResult {
+        let value = self.visit_expr(rhs)?;
+        self.eval_prefix_op(op, value, span)
+    }
+
+    fn eval_prefix_op(&self, op: &TokenKind, rhs: Value, span: Span) -> ValueResult {
+        Ok(match (op, rhs) {
+            (TokenKind::Minus, Value::Int(n)) => return self.overflow.negate(n, span),
+            (TokenKind::Minus, Value::BigInt(n)) => normalize(-n),
+            (TokenKind::Minus, Value::Float(x)) => Value::Float(-x),
+            (TokenKind::Not, rhs) => Value::Bool(!bool::from(rhs)),
+            (op, rhs) => {
+                return Err(FerError::Runtime(
+                    span,
+                    format!("Cannot apply {} to {}", op, rhs.type_name()),
+                ))
+            }
         })
     }
 
-    fn visit_set(&mut self, name: &str, expr: &Expr) -> StmtResult {
-        let name = name.to_string();
-        if &name == "pop" {
-            return Err("Error: Cannot assign to reserved identifier 'pop'".to_string());
+    fn visit_set(&mut self, var: &Var, expr: &Expr) -> StmtResult {
+        let value = self.visit_expr(expr)?;
+        self.env.set(var.slot(), value);
+        Ok(())
+    }
+
+    fn visit_set_index(&mut self, var: &Var, path: &[Expr], expr: &Expr, span: Span) -> StmtResult {
+        let path = path
+            .iter()
+            .map(|index| self.visit_expr(index))
+            .collect::<FerResult<Vec<_>>>()?;
+        let value = self.visit_expr(expr)?;
+        self.assign_index(var.slot(), &path, value, span)
+    }
+
+    /// Assign `value` to the element of the variable in `slot` at the end of `path`
+    fn assign_index(&mut self, slot: Slot, path: &[Value], value: Value, span: Span) -> StmtResult {
+        // the parser makes sure there's at least one index
+        let (last, path) = path.split_last().unwrap();
+        let mut target = self.env.get_mut(slot, span)?;
+        for index in path {
+            target = index::index_mut(target, index, span)?;
         }
+        index::assign(target, last, value, span)
+    }
+
+    fn visit_append(&mut self, var: &Var, expr: &Expr, span: Span) -> StmtResult {
         let value = self.visit_expr(expr)?;
-        self.env.set(name, value);
+        self.append(var.slot(), value, span)
+    }
+
+    fn append(&mut self, slot: Slot, value: Value, span: Span) -> StmtResult {
+        match self.env.get_mut(slot, span)? {
+            Value::List(items) => {
+                Rc::make_mut(items).push(value);
+                Ok(())
+            }
+            target => Err(FerError::Runtime(
+                span,
+                format!("Cannot append to a {}", target.type_name()),
+            )),
+        }
+    }
+
+    fn visit_remove(&mut self, var: &Var, index: &Expr, span: Span) -> StmtResult {
+        let index = self.visit_expr(index)?;
+        let target = self.env.get_mut(var.slot(), span)?;
+        index::remove(target, &index, span)?;
         Ok(())
     }
 
@@ -136,8 +389,151 @@ impl Interpreter {
         Ok(())
     }
 
-    fn visit_pop(&mut self) -> StmtResult {
-        self.env.pop()?;
+    fn visit_pop(&mut self, span: Span) -> StmtResult {
+        self.env.pop(span)?;
+        Ok(())
+    }
+
+    fn visit_list(&mut self, items: &[Expr]) -> ValueResult {
+        let items = items
+            .iter()
+            .map(|item| self.visit_expr(item))
+            .collect::<FerResult<Vec<_>>>()?;
+        Ok(Value::List(Rc::new(items)))
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)]) -> ValueResult {
+        let mut map = BTreeMap::new();
+        for (key, value) in entries {
+            let span = key.span;
+            let key = self.visit_expr(key)?;
+            let value = self.visit_expr(value)?;
+            map.insert(index::key(&key, span)?.to_string(), value);
+        }
+        Ok(Value::Map(Rc::new(map)))
+    }
+
+    fn visit_index(&mut self, expr: &Expr, index: &Expr, span: Span) -> ValueResult {
+        let value = self.visit_expr(expr)?;
+        let index = self.visit_expr(index)?;
+        index::index(&value, &index, span)
+    }
+
+    fn visit_slice(
+        &mut self,
+        expr: &Expr,
+        from: &Option<Box<Expr>>,
+        to: &Option<Box<Expr>>,
+        span: Span,
+    ) -> ValueResult {
+        let value = self.visit_expr(expr)?;
+        let from = from
+            .as_ref()
+            .map(|from| self.visit_expr(from))
+            .transpose()?;
+        let to = to.as_ref().map(|to| self.visit_expr(to)).transpose()?;
+        index::slice(&value, from, to, span)
+    }
+
+    fn visit_stack_index(&mut self, index: &Expr, span: Span) -> ValueResult {
+        let n = self.stack_offset("A stack index", index)?;
+        self.env.peek(n, span)
+    }
+
+    fn visit_pick(&mut self, expr: &Expr, span: Span) -> StmtResult {
+        let n = self.stack_offset("The argument of pick", expr)?;
+        self.env.pick(n, span)
+    }
+
+    /// Evaluate how many places below the top of the stack to look, `what` is used for errors
+    fn stack_offset(&mut self, what: &str, expr: &Expr) -> FerResult<usize> {
+        let value = self.visit_expr(expr)?;
+        offset(what, &value, expr.span)
+    }
+
+    fn visit_commentop(&mut self, lines: &SpanLit, span: Span) -> StmtResult {
+        let text = self.program().comment_out(*lines, span)?;
+        self.env.push(Value::comment(text));
+        Ok(())
+    }
+
+    fn visit_uncomment(&mut self, var: &Var, span: Span) -> StmtResult {
+        let lines = self.comment_lines(var, span)?;
+        self.program().insert_next(lines);
+        Ok(())
+    }
+
+    fn visit_copy(&mut self, var: &Var, address: HalfSpanLit, span: Span) -> StmtResult {
+        let comment = self.get_comment(var, span)?;
+        self.insert_comment(comment, address, span)
+    }
+
+    fn visit_move(&mut self, var: &Var, address: HalfSpanLit, span: Span) -> StmtResult {
+        let comment = self.get_comment(var, span)?;
+        self.insert_comment(comment, address, span)?;
+        self.env.remove(var.slot(), span)?;
+        Ok(())
+    }
+
+    fn visit_call(&mut self, var: &Var, span: Span) -> StmtResult {
+        if self.call_stack.len() >= MAX_CALL_DEPTH {
+            return Err(FerError::Runtime(
+                span,
+                format!(
+                    "Maximum call depth of {} exceeded while calling {}",
+                    MAX_CALL_DEPTH, var
+                ),
+            ));
+        }
+        let lines = self.comment_lines(var, span)?;
+        self.call_stack.push(Program::new(lines));
+        Ok(())
+    }
+
+    fn visit_conditional(&mut self, guard: &Option<Expr>, stmt: &Stmt, span: Span) -> StmtResult {
+        let condition = match guard {
+            Some(guard) => self.visit_expr(guard)?,
+            None => self.env.pop(span)?,
+        };
+        if bool::from(condition) {
+            self.visit_stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    /// Read a line of input, it's an error if there's none left
+    fn read_line(&mut self, span: Span) -> FerResult<String> {
+        match self.input.read_line() {
+            Ok(Some(line)) => Ok(line),
+            Ok(None) => Err(FerError::Runtime(
+                span,
+                "Unexpected end of input".to_string(),
+            )),
+            Err(e) => Err(FerError::Runtime(
+                span,
+                format!("Couldn't read input: {}", e),
+            )),
+        }
+    }
+
+    fn visit_read(&mut self, slot: Slot, span: Span) -> StmtResult {
+        let line = self.read_line(span)?;
+        self.env.set(slot, Value::Str(line.into()));
+        Ok(())
+    }
+
+    fn visit_readint(&mut self, slot: Slot, span: Span) -> StmtResult {
+        let line = self.read_line(span)?;
+        let n = line.trim().parse().map_err(|_| {
+            FerError::Runtime(
+                span,
+                format!(
+                    "Expected an integer, got {}",
+                    Value::Str(line.as_str().into()).repr()
+                ),
+            )
+        })?;
+        self.env.set(slot, Value::Int(n));
         Ok(())
     }
 
@@ -148,79 +544,219 @@ impl Interpreter {
     }
 }
 
+/// Check that `value` can be used to look `what` places below the top of the stack
+fn offset(what: &str, value: &Value, span: Span) -> FerResult<usize> {
+    match value {
+        Value::Int(n) if *n >= 0 => Ok(*n as usize),
+        value => Err(FerError::Runtime(
+            span,
+            format!(
+                "{} must be a non-negative integer, got {}",
+                what,
+                value.repr()
+            ),
+        )),
+    }
+}
+
+/// Evaluate `op` on two numbers where at least one is a float, promoting the other to a float.
+/// Follows IEEE 754, so dividing by zero gives infinity or NaN rather than an error.
+fn eval_infix_float(op: &TokenKind, lhs: Value, rhs: Value, span: Span) -> ValueResult {
+    let (a, b) = match (lhs.to_float(), rhs.to_float()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Err(type_error(op, &lhs, &rhs, span)),
+    };
+    Ok(match op {
+        TokenKind::Plus => Value::Float(a + b),
+        TokenKind::Minus => Value::Float(a - b),
+        TokenKind::Multiply => Value::Float(a * b),
+        TokenKind::Divide => Value::Float(a / b),
+        _ => compare(op, &a, &b),
+    })
+}
+
+fn eval_infix_string(op: &TokenKind, lhs: Value, rhs: Value, span: Span) -> ValueResult {
+    match (op, lhs, rhs) {
+        (TokenKind::Plus, Value::Str(lhs), Value::Str(rhs)) => {
+            Ok(Value::Str(format!("{}{}", lhs, rhs).into()))
+        }
+        (TokenKind::Multiply, Value::Str(s), Value::Int(n)) => repeat(&s, n, span),
+        (
+            TokenKind::Less | TokenKind::LessEq | TokenKind::Greater | TokenKind::GreaterEq,
+            Value::Str(lhs),
+            Value::Str(rhs),
+        ) => Ok(compare(op, &lhs, &rhs)),
+        (_, lhs, rhs) => Err(type_error(op, &lhs, &rhs, span)),
+    }
+}
+
+/// Evaluate the comparison operator `op` on two values of the same type
+fn compare<T: PartialOrd + ?Sized>(op: &TokenKind, lhs: &T, rhs: &T) -> Value {
+    Value::Bool(match op {
+        TokenKind::Equals => lhs == rhs,
+        TokenKind::NotEq => lhs != rhs,
+        TokenKind::Less => lhs < rhs,
+        TokenKind::LessEq => lhs <= rhs,
+        TokenKind::Greater => lhs > rhs,
+        TokenKind::GreaterEq => lhs >= rhs,
+        _ => unreachable!(),
+    })
+}
+
+fn repeat(s: &str, times: i64, span: Span) -> ValueResult {
+    let times = usize::try_from(times).map_err(|_| {
+        FerError::Runtime(
+            span,
+            "Cannot repeat a string a negative number of times".to_string(),
+        )
+    })?;
+    match s.len().checked_mul(times) {
+        Some(len) if len <= MAX_REPEAT_LEN => Ok(Value::Str(s.repeat(times).into())),
+        _ => Err(FerError::Runtime(
+            span,
+            "String repetition too large".to_string(),
+        )),
+    }
+}
+
+fn type_error(op: &TokenKind, lhs: &Value, rhs: &Value, span: Span) -> FerError {
+    FerError::Runtime(
+        span,
+        format!(
+            "Cannot apply {} to {} and {}",
+            op,
+            lhs.type_name(),
+            rhs.type_name()
+        ),
+    )
+}
+
 impl Visitor for Interpreter {
     fn visit_expr(&mut self, expr: &Expr) -> ValueResult {
-        match expr {
-            Expr::Literal(lit) => Ok(Self::visit_literal(lit)),
-            Expr::Ident(name) => self.visit_ident(name),
-            Expr::BinaryOp(op, lhs, rhs) => self.visit_binary_op(op, lhs, rhs),
-            Expr::UnaryOp(op, rhs) => self.visit_unary_op(op, rhs),
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Literal(lit) => Ok(Self::visit_literal(lit)),
+            ExprKind::Ident(var) => self.env.get(var.slot(), span),
+            ExprKind::Pop => self.env.pop(span),
+            ExprKind::BinaryOp(op, lhs, rhs) => self.visit_binary_op(op, lhs, rhs, span),
+            ExprKind::UnaryOp(op, rhs) => self.visit_unary_op(op, rhs, span),
+            ExprKind::Call(name, args) => self.visit_builtin_call(name, args, span),
+            // a stack can't realistically grow past `i64::MAX` values
+            ExprKind::Depth => Ok(Value::Int(self.env.depth() as i64)),
+            ExprKind::Peek => self.env.peek(0, span),
+            ExprKind::StackIndex(index) => self.visit_stack_index(index, span),
+            ExprKind::List(items) => self.visit_list(items),
+            ExprKind::Map(entries) => self.visit_map(entries),
+            ExprKind::Index(expr, index) => self.visit_index(expr, index, span),
+            ExprKind::Slice(expr, from, to) => self.visit_slice(expr, from, to, span),
+            ExprKind::Comment(comment) => Ok(Value::Comment(Rc::clone(comment))),
         }
     }
 
     fn visit_stmt(&mut self, stmt: &Stmt) -> StmtResult {
-        match stmt {
-            Stmt::Set(name, expr) => self.visit_set(name, expr),
-            Stmt::Push(expr) => self.visit_push(expr),
-            Stmt::Check(expr) => self.visit_check(expr),
-            Stmt::Pop => self.visit_pop(),
-            Stmt::Print(expr) => self.visit_print(expr),
+        let span = stmt.span;
+        match &stmt.kind {
+            StmtKind::Set(var, expr) => self.visit_set(var, expr),
+            StmtKind::SetIndex(var, path, expr) => self.visit_set_index(var, path, expr, span),
+            StmtKind::Append(var, expr) => self.visit_append(var, expr, span),
+            StmtKind::Remove(var, index) => self.visit_remove(var, index, span),
+            StmtKind::Push(expr) => self.visit_push(expr),
+            StmtKind::Check(expr) => self.visit_check(expr),
+            StmtKind::Pop | StmtKind::Drop => self.visit_pop(span),
+            StmtKind::Dup => self.env.dup(span),
+            StmtKind::Swap => self.env.swap(span),
+            StmtKind::Over => self.env.over(span),
+            StmtKind::Rot => self.env.rot(span),
+            StmtKind::Clear => {
+                self.env.clear();
+                Ok(())
+            }
+            StmtKind::Pick(expr) => self.visit_pick(expr, span),
+            StmtKind::Print(expr) => self.visit_print(expr),
+            StmtKind::Read(var) => self.visit_read(var.slot(), span),
+            StmtKind::ReadInt(var) => self.visit_readint(var.slot(), span),
+            StmtKind::WriteFile(path, text) => self.visit_write_file(path, text, false, span),
+            StmtKind::AppendFile(path, text) => self.visit_write_file(path, text, true, span),
+            StmtKind::CommentOp(lines) => self.visit_commentop(lines, span),
+            StmtKind::Uncomment(var) => self.visit_uncomment(var, span),
+            StmtKind::Copy(var, address) => self.visit_copy(var, *address, span),
+            StmtKind::Move(var, address) => self.visit_move(var, *address, span),
+            StmtKind::Call(var) => self.visit_call(var, span),
+            StmtKind::Include(path, module) => {
+                self.visit_include(path, module.as_ref().map(Var::slot), span)
+            }
+            StmtKind::Conditional(guard, stmt) => self.visit_conditional(guard, stmt, span),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{collections::BTreeMap, io::Cursor, ptr, rc::Rc};
+
     use crate::{
-        interpreter::{Interpreter, Visitor},
-        lexer::TokenKind,
-        parser::{Expr, Lit},
+        error::FerError,
+        interpreter::{program::Program, Interpreter, Visitor},
+        lexer::{Span, TokenKind, MAX_COMMENT_DEPTH},
+        parser::{Comment, Expr, ExprKind, Lit, Parser, StmtKind},
+        resolver::Interner,
     };
 
-    use super::value::Value;
+    use super::value::{CommentValue, Value};
 
     #[test]
     fn test_integer_literal() {
-        let input = Expr::Literal(Lit::Int(5));
+        let input = literal(Lit::Int(5));
         let expected = Value::Int(5);
         test_expr(input, expected);
     }
 
     #[test]
     fn test_boolean_literal() {
-        let input = Expr::Literal(Lit::Bool(true));
+        let input = literal(Lit::Bool(true));
         let expected = Value::Bool(true);
         test_expr(input, expected);
-        let input = Expr::Literal(Lit::Bool(false));
+        let input = literal(Lit::Bool(false));
         let expected = Value::Bool(false);
         test_expr(input, expected);
     }
 
     #[test]
     fn test_string_literal() {
-        let input = Expr::Literal(Lit::Str("hello".to_string()));
-        let expected = Value::Str("hello".to_string());
+        let input = literal(Lit::Str("hello".to_string()));
+        let expected = Value::Str("hello".into());
         test_expr(input, expected);
     }
 
     #[test]
     fn test_binary_op() {
-        let input = Expr::BinaryOp(
-            TokenKind::Plus,
-            Box::new(Expr::Literal(Lit::Int(5))),
-            Box::new(Expr::Literal(Lit::Int(10))),
-        );
+        let input = binary(TokenKind::Plus, literal(Lit::Int(5)), literal(Lit::Int(10)));
         let expected = Value::Int(15);
         test_expr(input, expected);
     }
 
     #[test]
     fn test_unary_op() {
-        let input = Expr::UnaryOp(TokenKind::Minus, Box::new(Expr::Literal(Lit::Int(5))));
+        let input = unary(TokenKind::Minus, literal(Lit::Int(5)));
         let expected = Value::Int(-5);
         test_expr(input, expected);
     }
 
+    fn literal(lit: Lit) -> Expr {
+        Expr::new(ExprKind::Literal(lit), (0..0).into())
+    }
+
+    fn binary(op: TokenKind, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::new(
+            ExprKind::BinaryOp(op, Box::new(lhs), Box::new(rhs)),
+            (0..0).into(),
+        )
+    }
+
+    fn unary(op: TokenKind, rhs: Expr) -> Expr {
+        Expr::new(ExprKind::UnaryOp(op, Box::new(rhs)), (0..0).into())
+    }
+
     fn test_expr(input: Expr, expected: Value) {
         let mut interpreter = Interpreter::new();
         let actual = interpreter.visit_expr(&input);
@@ -228,6 +764,59 @@ mod tests {
         assert_eq!(actual.unwrap(), expected);
     }
 
+    #[test]
+    fn test_runtime_error_span() {
+        let program = Program::new(Program::parse_lines("print 1\nprint 1 + x").unwrap());
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            interpreter.run(program),
+            Err(FerError::Runtime(
+                Span { start: 18, end: 19 },
+                "x is undefined".to_string()
+            ))
+        );
+
+        let program = Program::new(Program::parse_lines("push 1\npop\npop").unwrap());
+        assert_eq!(
+            interpreter.run(program),
+            Err(FerError::Runtime(
+                Span { start: 11, end: 14 },
+                "The stack is empty".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_string_ops() {
+        let hello = || literal(Lit::Str("hello".to_string()));
+        let input = binary(TokenKind::Plus, hello(), literal(Lit::Str("!".to_string())));
+        test_expr(input, Value::Str("hello!".into()));
+        let input = binary(TokenKind::Multiply, hello(), literal(Lit::Int(2)));
+        test_expr(input, Value::Str("hellohello".into()));
+        let input = binary(
+            TokenKind::Less,
+            hello(),
+            literal(Lit::Str("help".to_string())),
+        );
+        test_expr(input, Value::Bool(true));
+
+        let mut interpreter = Interpreter::new();
+        let input = binary(TokenKind::Minus, hello(), literal(Lit::Int(2)));
+        assert!(interpreter.visit_expr(&input).is_err());
+        let input = binary(TokenKind::Multiply, hello(), literal(Lit::Int(-1)));
+        assert!(interpreter.visit_expr(&input).is_err());
+        let input = binary(TokenKind::Multiply, hello(), literal(Lit::Int(i64::MAX)));
+        assert!(interpreter.visit_expr(&input).is_err());
+        let input = binary(
+            TokenKind::Multiply,
+            hello(),
+            literal(Lit::Int(99_999_999_999_999)),
+        );
+        assert!(interpreter.visit_expr(&input).is_err());
+        let input = unary(TokenKind::Minus, hello());
+        assert!(interpreter.visit_expr(&input).is_err());
+    }
+
     #[test]
     fn test_integer_equals() {
         let expected = Value::Int(5);
@@ -244,16 +833,256 @@ mod tests {
         assert_ne!(actual, Value::Bool(false));
     }
 
+    #[test]
+    fn test_string_and_comment_equals() {
+        let comment = |text: &str| Value::comment(text);
+        assert_eq!(comment("print 1"), comment("print 1"));
+        assert_ne!(comment("print 1"), comment("print 2"));
+        assert_eq!(Value::Str("a".into()), Value::Str("a".into()));
+        assert_ne!(Value::Str("a".into()), Value::Str("b".into()));
+    }
+
+    #[test]
+    fn test_different_types_are_unequal() {
+        assert_ne!(Value::Int(1), Value::Bool(true));
+        assert_ne!(Value::Int(1), Value::Str("1".into()));
+        assert_ne!(Value::Str("print 1".into()), Value::comment("print 1"));
+        let input = binary(
+            TokenKind::Equals,
+            literal(Lit::Int(0)),
+            literal(Lit::Bool(false)),
+        );
+        test_expr(input, Value::Bool(false));
+    }
+
+    #[test]
+    fn test_truthiness() {
+        let truthy = |value: Value| bool::from(value);
+        assert!(truthy(Value::Bool(true)));
+        assert!(!truthy(Value::Bool(false)));
+        assert!(truthy(Value::Int(-1)));
+        assert!(!truthy(Value::Int(0)));
+        assert!(truthy(Value::Str("a".into())));
+        assert!(!truthy(Value::Str("".into())));
+        assert!(truthy(Value::comment("print 1")));
+        assert!(!truthy(Value::comment(" \n")));
+    }
+
+    #[test]
+    fn test_comment_parsed_once() {
+        let comment = Value::comment("print 1\nprint 2");
+        let (a, b) = match (&comment, comment.clone()) {
+            (Value::Comment(a), Value::Comment(b)) => (Rc::clone(a), b),
+            _ => unreachable!(),
+        };
+        // copies share the comment, so only one of them ever parses it
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(ptr::eq(a.body(), b.body()));
+        match a.body() {
+            Comment::Valid(stmts) => assert_eq!(stmts.len(), 2),
+            body => panic!("unexpected body {:?}", body),
+        }
+        assert!(matches!(
+            CommentValue::new("print").body(),
+            Comment::Invalid(_)
+        ));
+        assert!(matches!(CommentValue::new(" \n").body(), Comment::Empty));
+    }
+
+    #[test]
+    fn test_comment_literal_reuses_parse() {
+        let (lines, _) = Parser::new("push {print 1}").parse_all();
+        let expr = match &lines[0].0.kind {
+            StmtKind::Push(expr) => expr,
+            kind => panic!("unexpected statement {:?}", kind),
+        };
+        let literal = match &expr.kind {
+            ExprKind::Comment(comment) => comment,
+            kind => panic!("unexpected expression {:?}", kind),
+        };
+        // every evaluation of the literal shares it, text, code and all
+        let mut interpreter = Interpreter::new();
+        for _ in 0..2 {
+            match interpreter.visit_expr(expr).unwrap() {
+                Value::Comment(comment) => assert!(Rc::ptr_eq(&comment, literal)),
+                value => panic!("unexpected value {:?}", value),
+            }
+        }
+    }
+
+    #[test]
+    fn test_comment_lines_cached() {
+        let comment = CommentValue::new("print x\nprint y");
+        let mut interner = Interner::default();
+        let lines = comment.lines(&mut interner).unwrap();
+        assert!(Rc::ptr_eq(&lines, &comment.lines(&mut interner).unwrap()));
+        // copies of the lines share their statements and chunks
+        let copy = lines.to_vec();
+        assert!(Rc::ptr_eq(&copy[0].stmt, &lines[0].stmt));
+        assert!(Rc::ptr_eq(&copy[1].chunk(), &lines[1].chunk()));
+
+        // another interner gives the variables other slots
+        let mut other = Interner::default();
+        other.intern("y");
+        let resolved = comment.lines(&mut other).unwrap();
+        assert!(!Rc::ptr_eq(&lines, &resolved));
+        assert_ne!(resolved[0].stmt, lines[0].stmt);
+
+        assert!(CommentValue::new("print").lines(&mut interner).is_err());
+    }
+
+    #[test]
+    fn test_copy_shares_comment() {
+        let program = "{print 1}\nset c pop\ncopy c [+1]\nset d pop";
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .run(Program::new(Program::parse_lines(program).unwrap()))
+            .unwrap();
+        let vars: BTreeMap<_, _> = interpreter.env().vars().into_iter().collect();
+        match (vars["c"], vars["d"]) {
+            (Value::Comment(c), Value::Comment(d)) => assert!(Rc::ptr_eq(c, d)),
+            vars => panic!("unexpected values {:?}", vars),
+        }
+    }
+
+    #[test]
+    fn test_copy_deepest_comment() {
+        // commenting out the line nests the literal in it one level deeper than it can be written
+        let depth = MAX_COMMENT_DEPTH as usize;
+        let literal = format!("{}{}", "{".repeat(depth), "}".repeat(depth));
+        let program = format!(
+            "set z {}\ncomment [-1:-1]\nset c pop\ncopy c [+1]\nset d pop\nuncomment d",
+            literal
+        );
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .run(Program::new(Program::parse_lines(&program).unwrap()))
+            .unwrap();
+        let vars: BTreeMap<_, _> = interpreter.env().vars().into_iter().collect();
+        assert_eq!(vars["z"].repr(), literal);
+    }
+
+    #[test]
+    fn test_float_ops() {
+        let float = |x| literal(Lit::Float(x));
+        test_expr(
+            binary(TokenKind::Multiply, float(1.5), float(2.0)),
+            Value::Float(3.0),
+        );
+        // integers are promoted on either side
+        test_expr(
+            binary(TokenKind::Divide, literal(Lit::Int(3)), float(2.0)),
+            Value::Float(1.5),
+        );
+        test_expr(
+            binary(TokenKind::Minus, float(0.5), literal(Lit::Int(1))),
+            Value::Float(-0.5),
+        );
+        test_expr(
+            binary(TokenKind::Less, literal(Lit::Int(1)), float(1.5)),
+            Value::Bool(true),
+        );
+        test_expr(unary(TokenKind::Minus, float(2.5)), Value::Float(-2.5));
+        test_expr(
+            binary(TokenKind::Divide, float(1.0), literal(Lit::Int(0))),
+            Value::Float(f64::INFINITY),
+        );
+
+        let mut interpreter = Interpreter::new();
+        let input = binary(TokenKind::Plus, float(1.0), literal(Lit::Bool(true)));
+        assert!(interpreter.visit_expr(&input).is_err());
+    }
+
+    #[test]
+    fn test_int_float_equality() {
+        assert_eq!(Value::Int(1), Value::Float(1.0));
+        assert_ne!(Value::Float(1.5), Value::Int(1));
+        assert!(!bool::from(Value::Float(0.0)));
+        assert!(bool::from(Value::Float(0.1)));
+    }
+
+    #[test]
+    fn test_conversions() {
+        let call = |name: &str, arg| {
+            Expr::new(
+                ExprKind::Call(name.to_string(), vec![literal(arg)]),
+                (0..0).into(),
+            )
+        };
+        test_expr(call("int", Lit::Float(-2.7)), Value::Int(-2));
+        test_expr(call("int", Lit::Str(" 12 ".to_string())), Value::Int(12));
+        test_expr(call("float", Lit::Int(3)), Value::Float(3.0));
+        test_expr(
+            call("float", Lit::Str("1e2".to_string())),
+            Value::Float(100.0),
+        );
+
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter
+            .visit_expr(&call("int", Lit::Str("1.5".to_string())))
+            .is_err());
+        assert!(interpreter
+            .visit_expr(&call("float", Lit::Bool(true)))
+            .is_err());
+        assert!(interpreter
+            .visit_expr(&call("int", Lit::Float(f64::NAN)))
+            .is_err());
+        let no_args = Expr::new(ExprKind::Call("int".to_string(), vec![]), (0..0).into());
+        assert!(interpreter.visit_expr(&no_args).is_err());
+    }
+
+    #[test]
+    fn test_read_from_injected_input() {
+        let source = "read a\nreadint b\ncheck eof()\npush a * b";
+        let program = Program::new(Program::parse_lines(source).unwrap());
+        let mut interpreter = Interpreter::new();
+        interpreter.set_input(Cursor::new("ab\n2\n"));
+        interpreter.run(program).unwrap();
+        assert_eq!(
+            interpreter.env().stack(),
+            &[Value::Bool(true), Value::Str("abab".into())]
+        );
+
+        let program = Program::new(Program::parse_lines("read a").unwrap());
+        assert_eq!(
+            interpreter.run(program),
+            Err(FerError::Runtime(
+                Span { start: 0, end: 6 },
+                "Unexpected end of input".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_write_and_append_files() {
+        let path = std::env::temp_dir().join(format!("ferette-test-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap().replace('\\', "/");
+        let source = format!(
+            "writefile \"{0}\", \"a\"\nappendfile \"{0}\", [1]\npush readfile(\"{0}\")",
+            path
+        );
+        let program = Program::new(Program::parse_lines(&source).unwrap());
+        let mut interpreter = Interpreter::new();
+        interpreter.run(program).unwrap();
+        assert_eq!(interpreter.env().stack(), &[Value::Str("a[1]".into())]);
+        std::fs::remove_file(&path).unwrap();
+
+        interpreter.set_fs_access(false);
+        let program = Program::new(Program::parse_lines(&source).unwrap());
+        assert!(interpreter.run(program).is_err());
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
     #[test]
     fn test_and_or() {
-        let input = Expr::BinaryOp(
+        let input = binary(
             TokenKind::Or,
-            Box::new(Expr::BinaryOp(
+            binary(
                 TokenKind::And,
-                Box::new(Expr::Literal(Lit::Bool(true))),
-                Box::new(Expr::Literal(Lit::Bool(true))),
-            )),
-            Box::new(Expr::Literal(Lit::Bool(false))),
+                literal(Lit::Bool(true)),
+                literal(Lit::Bool(true)),
+            ),
+            literal(Lit::Bool(false)),
         );
         let expected = Value::Bool(true);
         test_expr(input, expected);
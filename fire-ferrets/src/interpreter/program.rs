@@ -0,0 +1,304 @@
+use std::{cell::OnceCell, rc::Rc};
+
+use super::compiler::Chunk;
+use crate::{
+    error::{FerError, FerResult},
+    lexer::Span,
+    parser::{Comment, HalfSpanLit, NumKind, Parser, SpanLit, Stmt},
+    resolver::Resolver,
+};
+
+/// A single line of a running program.
+/// Copies of a line, like the ones a comment makes each time it's called,
+/// share its statement, source and compiled chunk.
+#[derive(Clone, Debug)]
+pub struct Line {
+    pub stmt: Rc<Stmt>,
+    /// The source text the line was parsed from
+    pub source: Rc<str>,
+    /// Commented out lines are skipped when running the program
+    pub commented: bool,
+    /// Whether the line was created by a comment operation rather than read from the input,
+    /// in which case its spans are relative to `source` instead of the input
+    pub generated: bool,
+    /// Whether the variables in `stmt` have slots yet, which they get the first time it's run
+    resolved: bool,
+    /// The line compiled for the VM, which happens the first time it's run
+    chunk: Rc<OnceCell<Rc<Chunk>>>,
+}
+
+impl Line {
+    pub fn new(stmt: Stmt, source: &str) -> Self {
+        Self {
+            stmt: Rc::new(stmt),
+            source: source.into(),
+            commented: false,
+            generated: false,
+            resolved: false,
+            chunk: Rc::default(),
+        }
+    }
+
+    pub fn generated(stmt: Stmt, source: &str) -> Self {
+        Self {
+            generated: true,
+            ..Self::new(stmt, source)
+        }
+    }
+
+    /// Give the variables in the line slots, unless they already have them
+    pub fn resolve(&mut self, resolver: &mut Resolver) {
+        if !self.resolved {
+            resolver.resolve(Rc::make_mut(&mut self.stmt));
+            // the statement may no longer be the one copies of the line compiled
+            self.chunk = Rc::default();
+            self.resolved = true;
+        }
+    }
+
+    /// The compiled form of the line. Comment operations only insert new lines
+    /// or comment out existing ones, so a line never has to be compiled twice.
+    pub fn chunk(&self) -> Rc<Chunk> {
+        let chunk = self
+            .chunk
+            .get_or_init(|| Rc::new(Chunk::compile(&self.stmt)));
+        Rc::clone(chunk)
+    }
+}
+
+/// An editable buffer of lines along with a program counter pointing to the line being executed.
+/// Comment operations modify it while it's running, so every edit keeps `pc` on the same line.
+#[derive(Debug, Default)]
+pub struct Program {
+    lines: Vec<Line>,
+    pc: usize,
+}
+
+impl Program {
+    pub fn new(lines: Vec<Line>) -> Self {
+        Self { lines, pc: 0 }
+    }
+
+    /// Parse source text (for example the text of a comment) into lines of code
+    pub fn parse_lines(text: &str) -> FerResult<Vec<Line>> {
+        Self::comment_lines(&Parser::comment_body(text))
+    }
+
+    /// The lines of code in a parsed comment, or the first error in it
+    pub fn comment_lines(comment: &Comment) -> FerResult<Vec<Line>> {
+        match comment {
+            Comment::Valid(stmts) => Ok(stmts
+                .iter()
+                .map(|(stmt, source)| Line::generated(stmt.clone(), source))
+                .collect()),
+            Comment::Invalid(e) => Err(e.clone()),
+            Comment::Empty => Ok(vec![]),
+        }
+    }
+
+    /// The line at the program counter, or `None` once the program has finished
+    pub fn current(&self) -> Option<&Line> {
+        self.lines.get(self.pc)
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut Line> {
+        self.lines.get_mut(self.pc)
+    }
+
+    /// Move the program counter to the next line
+    pub fn advance(&mut self) {
+        self.pc += 1;
+    }
+
+    /// Turn a line address into an index into the buffer.
+    /// Absolute addresses are 1 based, relative ones are relative to the current line.
+    fn index(&self, address: HalfSpanLit) -> Option<usize> {
+        let HalfSpanLit(kind, n) = address;
+        match kind {
+            NumKind::Abs => n.checked_sub(1),
+            NumKind::Pos => self.pc.checked_add(n),
+            NumKind::Neg => self.pc.checked_sub(n),
+        }
+    }
+
+    /// Resolve an address that has to point to an existing line
+    pub fn resolve_line(&self, address: HalfSpanLit, span: Span) -> FerResult<usize> {
+        match self.index(address) {
+            Some(index) if index < self.lines.len() => Ok(index),
+            _ => Err(out_of_bounds(address, span)),
+        }
+    }
+
+    /// Resolve an address that new lines will be inserted at,
+    /// which may be one past the last line
+    pub fn resolve_insert(&self, address: HalfSpanLit, span: Span) -> FerResult<usize> {
+        match self.index(address) {
+            Some(index) if index <= self.lines.len() => Ok(index),
+            _ => Err(out_of_bounds(address, span)),
+        }
+    }
+
+    /// Comment out every line in `span` (inclusive on both ends),
+    /// returning their source text joined by newlines.
+    /// `span` is the span of the statement doing the commenting, for errors.
+    pub fn comment_out(&mut self, lines: SpanLit, span: Span) -> FerResult<String> {
+        let start = self.resolve_line(lines.0, span)?;
+        let end = self.resolve_line(lines.1, span)?;
+        if start > end {
+            return Err(FerError::Runtime(
+                span,
+                format!("Invalid line span {}", lines),
+            ));
+        }
+
+        let lines = &mut self.lines[start..=end];
+        for line in lines.iter_mut() {
+            line.commented = true;
+        }
+        Ok(lines
+            .iter()
+            .map(|line| &*line.source)
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    /// Insert `lines` so that the first one ends up at `index`
+    pub fn insert(&mut self, index: usize, lines: Vec<Line>) {
+        if index <= self.pc {
+            self.pc += lines.len();
+        }
+        self.lines.splice(index..index, lines);
+    }
+
+    /// Insert `lines` right after the current line, so that they're executed next
+    pub fn insert_next(&mut self, lines: Vec<Line>) {
+        self.insert(self.pc + 1, lines);
+    }
+}
+
+fn out_of_bounds(address: HalfSpanLit, span: Span) -> FerError {
+    FerError::Runtime(
+        span,
+        format!("Line {}{} is out of bounds", address.0, address.1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{Line, Program};
+    use crate::{
+        lexer::Span,
+        parser::{HalfSpanLit, NumKind, SpanLit, Stmt, StmtKind},
+    };
+
+    const SPAN: Span = Span { start: 0, end: 0 };
+
+    fn line(source: &str) -> Line {
+        Line::new(Stmt::new(StmtKind::Pop, SPAN), source)
+    }
+
+    fn program(len: usize, pc: usize) -> Program {
+        let lines = (0..len).map(|i| line(&format!("pop {}", i))).collect();
+        let mut program = Program::new(lines);
+        for _ in 0..pc {
+            program.advance();
+        }
+        program
+    }
+
+    #[test]
+    fn test_resolve_line() {
+        let program = program(5, 2);
+        assert_eq!(
+            program.resolve_line(HalfSpanLit(NumKind::Abs, 1), SPAN),
+            Ok(0)
+        );
+        assert_eq!(
+            program.resolve_line(HalfSpanLit(NumKind::Pos, 2), SPAN),
+            Ok(4)
+        );
+        assert_eq!(
+            program.resolve_line(HalfSpanLit(NumKind::Neg, 2), SPAN),
+            Ok(0)
+        );
+        assert_eq!(
+            program.resolve_line(HalfSpanLit(NumKind::Pos, 0), SPAN),
+            Ok(2)
+        );
+        assert!(program
+            .resolve_line(HalfSpanLit(NumKind::Abs, 0), SPAN)
+            .is_err());
+        assert!(program
+            .resolve_line(HalfSpanLit(NumKind::Abs, 6), SPAN)
+            .is_err());
+        assert!(program
+            .resolve_line(HalfSpanLit(NumKind::Pos, 3), SPAN)
+            .is_err());
+        assert!(program
+            .resolve_line(HalfSpanLit(NumKind::Neg, 3), SPAN)
+            .is_err());
+    }
+
+    #[test]
+    fn test_resolve_insert() {
+        let program = program(5, 2);
+        assert_eq!(
+            program.resolve_insert(HalfSpanLit(NumKind::Abs, 6), SPAN),
+            Ok(5)
+        );
+        assert_eq!(
+            program.resolve_insert(HalfSpanLit(NumKind::Pos, 3), SPAN),
+            Ok(5)
+        );
+        assert!(program
+            .resolve_insert(HalfSpanLit(NumKind::Pos, 4), SPAN)
+            .is_err());
+    }
+
+    #[test]
+    fn test_insert_keeps_pc() {
+        let mut program = program(3, 1);
+        program.insert(0, vec![line("pop new")]);
+        assert_eq!(&*program.current().unwrap().source, "pop 1");
+        program.insert_next(vec![line("pop next")]);
+        program.advance();
+        assert_eq!(&*program.current().unwrap().source, "pop next");
+    }
+
+    #[test]
+    fn test_comment_out() {
+        let mut program = program(4, 1);
+        let span = SpanLit(HalfSpanLit(NumKind::Pos, 0), HalfSpanLit(NumKind::Abs, 3));
+        assert_eq!(
+            program.comment_out(span, SPAN),
+            Ok("pop 1\npop 2".to_string())
+        );
+        program.advance();
+        assert!(program.current().unwrap().commented);
+        program.advance();
+        assert!(!program.current().unwrap().commented);
+
+        let span = SpanLit(HalfSpanLit(NumKind::Abs, 2), HalfSpanLit(NumKind::Abs, 1));
+        assert!(program.comment_out(span, SPAN).is_err());
+    }
+
+    #[test]
+    fn test_edits_keep_compiled_lines() {
+        let mut program = program(3, 1);
+        let compiled: Vec<_> = program.lines.iter().map(Line::chunk).collect();
+        program.insert_next(vec![line("pop new")]);
+        program
+            .comment_out(
+                SpanLit(HalfSpanLit(NumKind::Abs, 1), HalfSpanLit(NumKind::Abs, 1)),
+                SPAN,
+            )
+            .unwrap();
+        assert!(program.lines[2].chunk.get().is_none());
+        let lines = [0, 1, 3].iter().map(|&i| &program.lines[i]);
+        for (line, chunk) in lines.zip(&compiled) {
+            assert!(Rc::ptr_eq(line.chunk.get().unwrap(), chunk));
+        }
+    }
+}
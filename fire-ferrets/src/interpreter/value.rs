@@ -1,22 +1,152 @@
-use std::fmt;
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::BTreeMap,
+    fmt,
+    rc::Rc,
+};
 
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use super::program::{Line, Program};
+use crate::{
+    error::FerResult,
+    parser::{Comment, Parser},
+    resolver::{Interner, Resolver},
+};
+
+/// Strings, comments, lists and maps are shared between copies of a value, so cloning one
+/// is cheap no matter how big it is. Lists and maps are copied the first time a shared one
+/// is modified, with `Rc::make_mut`.
 #[derive(Clone, Debug)]
 pub enum Value {
     Int(i64),
-    Str(String),
-    Comment(String),
+    /// Only used for integers outside the range of `i64`, when overflowing into big integers
+    BigInt(BigInt),
+    Float(f64),
+    Str(Rc<str>),
+    Comment(Rc<CommentValue>),
     Bool(bool),
+    List(Rc<Vec<Value>>),
+    /// Sorted by key, so that iterating over a map is deterministic
+    Map(Rc<BTreeMap<String, Value>>),
 }
 
 impl Value {
-    pub fn to_int(&self) -> Result<i64, String> {
+    pub fn comment(text: impl Into<String>) -> Self {
+        Value::Comment(Rc::new(CommentValue::new(text)))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) | Value::BigInt(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Str(_) => "string",
+            Value::Comment(_) => "comment",
+            Value::Bool(_) => "boolean",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+        }
+    }
+
+    /// The value of a number as a float, `None` for every other type
+    pub fn to_float(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::BigInt(n) => n.to_f64(),
+            Value::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /// Like `Display`, but strings are quoted and comments are in braces,
+    /// so that the type of the value is obvious
+    pub fn repr(&self) -> String {
         match self {
-            Value::Int(n) => Ok(*n),
-            _ => Err("Error: Expected numerical expression".to_string()),
+            Value::Str(s) => quote(s),
+            Value::Comment(c) => format!("{{{}}}", c.text()),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// The text of a comment, along with the code in it.
+/// Comment literals come with the code the parser found in them, other comments are parsed
+/// the first time their code is needed. Either way it's kept for every later use,
+/// by this value and every copy of it.
+#[derive(Debug)]
+pub struct CommentValue {
+    text: String,
+    body: OnceCell<Comment>,
+    /// The lines of code in the comment, resolved with the interner whose id they're kept with
+    lines: RefCell<Option<(u64, Rc<[Line]>)>>,
+}
+
+impl CommentValue {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            body: OnceCell::new(),
+            lines: RefCell::new(None),
+        }
+    }
+
+    /// A comment whose text has already been parsed into `body`
+    pub fn parsed(text: impl Into<String>, body: Comment) -> Self {
+        Self {
+            text: text.into(),
+            body: OnceCell::from(body),
+            lines: RefCell::new(None),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The code in the comment, parsed only the first time
+    pub fn body(&self) -> &Comment {
+        self.body.get_or_init(|| Parser::comment_body(&self.text))
+    }
+
+    /// The lines of code in the comment, with their variables resolved by `interner`.
+    /// They're only resolved again when a different interner asks for them,
+    /// and the lines share their statements and compiled chunks with every copy of them.
+    pub fn lines(&self, interner: &mut Interner) -> FerResult<Rc<[Line]>> {
+        if let Some((id, lines)) = &*self.lines.borrow() {
+            if *id == interner.id() {
+                return Ok(Rc::clone(lines));
+            }
+        }
+        let mut lines = Program::comment_lines(self.body())?;
+        let mut resolver = Resolver::new(interner);
+        for line in &mut lines {
+            line.resolve(&mut resolver);
         }
+        let lines: Rc<[Line]> = lines.into();
+        *self.lines.borrow_mut() = Some((interner.id(), Rc::clone(&lines)));
+        Ok(lines)
+    }
+}
+
+/// Comments are equal if their text is the same, their code follows from it
+impl PartialEq for CommentValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
     }
 }
 
+/// Turn `s` back into a string literal
+fn quote(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\t', "\\t")
+    )
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -24,50 +154,66 @@ impl fmt::Display for Value {
             "{}",
             match self {
                 Value::Int(v) => v.to_string(),
+                Value::BigInt(v) => v.to_string(),
+                // always has a decimal point or exponent, so floats can be told apart from integers
+                Value::Float(v) => format!("{:?}", v),
                 Value::Str(v) => v.to_string(),
-                Value::Comment(v) => v.to_string(),
+                Value::Comment(v) => v.text().to_string(),
                 Value::Bool(v) => v.to_string(),
+                Value::List(items) => {
+                    let items: Vec<_> = items.iter().map(Value::repr).collect();
+                    format!("[{}]", items.join(", "))
+                }
+                Value::Map(entries) if entries.is_empty() => "[:]".to_string(),
+                Value::Map(entries) => {
+                    let entries: Vec<_> = entries
+                        .iter()
+                        .map(|(key, value)| format!("{}: {}", quote(key), value.repr()))
+                        .collect();
+                    format!("[{}]", entries.join(", "))
+                }
             }
         )
     }
 }
 
+/// Values are only ever equal to values of the same type, except for integers and floats
+/// which are compared by their numeric value. Comments are equal if their text is the same,
+/// lists and maps if all of their elements are
 impl PartialEq<Value> for Value {
     fn eq(&self, other: &Self) -> bool {
-        match self {
-            Value::Int(i) => {
-                if let Value::Int(j) = other {
-                    i == j
-                } else {
-                    false
-                }
-            }
-            Value::Str(a) => {
-                if let Value::Str(b) = other {
-                    a == b
-                } else {
-                    false
-                }
-            }
-            Value::Comment(_) => todo!(),
-            Value::Bool(a) => {
-                if let Value::Bool(b) = other {
-                    a == b
-                } else {
-                    false
-                }
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Float(_), Value::Int(_) | Value::BigInt(_))
+            | (Value::Int(_) | Value::BigInt(_), Value::Float(_)) => {
+                self.to_float() == other.to_float()
             }
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Comment(a), Value::Comment(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            _ => false,
         }
     }
 }
 
+/// Truthiness: `false`, `0`, `0.0`, the empty string, empty lists and maps
+/// and comments without any code in them are falsy, everything else is truthy
 impl From<Value> for bool {
     fn from(value: Value) -> Self {
         match value {
             Value::Bool(b) => b,
             Value::Int(n) => n != 0_i64,
-            Value::Str(s) => s.is_empty(),
-            Value::Comment(_) => todo!(),
+            // never zero, that would fit in an `i64`
+            Value::BigInt(_) => true,
+            Value::Float(x) => x != 0.0,
+            Value::Str(s) => !s.is_empty(),
+            Value::Comment(c) => !matches!(c.body(), Comment::Empty),
+            Value::List(items) => !items.is_empty(),
+            Value::Map(entries) => !entries.is_empty(),
         }
     }
 }
@@ -0,0 +1,179 @@
+use std::{collections::BTreeMap, rc::Rc};
+
+use super::{
+    compiler::{Chunk, Op},
+    files::write_file,
+    index, offset, Interpreter, StmtResult, Value,
+};
+use crate::parser::SpanLit;
+
+impl Interpreter {
+    /// Run a compiled line, the bytecode counterpart of `visit_stmt`
+    pub(super) fn run_chunk(&mut self, chunk: &Chunk) -> StmtResult {
+        let mut operands = Operands(Vec::new());
+        let name = |n: u32| chunk.names[n as usize].as_str();
+        let var = |n: u32| &chunk.vars[n as usize];
+        let mut ip = 0;
+        while let Some(&op) = chunk.code.get(ip) {
+            let span = chunk.spans[ip];
+            ip += 1;
+            match op {
+                Op::Const(n) => operands.push(chunk.constants[n as usize].clone()),
+                Op::Load(slot) => operands.push(self.env.get(slot, span)?),
+                Op::PopStack => operands.push(self.env.pop(span)?),
+                Op::Binary(op) => {
+                    let rhs = operands.pop();
+                    let lhs = operands.pop();
+                    operands.push(self.eval_infix_expression(&op, lhs, rhs, span)?);
+                }
+                Op::Unary(op) => {
+                    let rhs = operands.pop();
+                    operands.push(self.eval_prefix_op(&op, rhs, span)?);
+                }
+                Op::Truthy => {
+                    let value = operands.pop();
+                    operands.push(Value::Bool(value.into()));
+                }
+                Op::ShortCircuit(on, to) => {
+                    if bool::from(operands.pop()) == on {
+                        operands.push(Value::Bool(on));
+                        ip = to as usize;
+                    }
+                }
+                Op::JumpUnless(to) => {
+                    if !bool::from(operands.pop()) {
+                        ip = to as usize;
+                    }
+                }
+                Op::Builtin(n, argc) => {
+                    let args = operands.split_off(argc);
+                    operands.push(self.call_builtin(name(n), args, span)?);
+                }
+                // a stack can't realistically grow past `i64::MAX` values
+                Op::Depth => operands.push(Value::Int(self.env.depth() as i64)),
+                Op::Peek => operands.push(self.env.peek(0, span)?),
+                Op::Offset(what) => {
+                    offset(what.what(), operands.top(), span)?;
+                }
+                Op::StackIndex => {
+                    let n = operands.offset();
+                    operands.push(self.env.peek(n, span)?);
+                }
+                Op::List(len) => {
+                    let items = operands.split_off(len);
+                    operands.push(Value::List(Rc::new(items)));
+                }
+                Op::MapKey => {
+                    let key = &operands.0[operands.0.len() - 2];
+                    index::key(key, span)?;
+                }
+                Op::Map(len) => {
+                    let entries = operands.split_off(len * 2);
+                    let mut map = BTreeMap::new();
+                    let mut entries = entries.into_iter();
+                    while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+                        // `MapKey` already checked the key
+                        map.insert(index::key(&key, span)?.to_string(), value);
+                    }
+                    operands.push(Value::Map(Rc::new(map)));
+                }
+                Op::Index => {
+                    let index = operands.pop();
+                    let value = operands.pop();
+                    operands.push(index::index(&value, &index, span)?);
+                }
+                Op::Slice(has_from, has_to) => {
+                    let to = if has_to { Some(operands.pop()) } else { None };
+                    let from = if has_from { Some(operands.pop()) } else { None };
+                    let value = operands.pop();
+                    operands.push(index::slice(&value, from, to, span)?);
+                }
+
+                Op::Set(slot) => self.env.set(slot, operands.pop()),
+                Op::SetIndex(slot, len) => {
+                    let value = operands.pop();
+                    let path = operands.split_off(len);
+                    self.assign_index(slot, &path, value, span)?;
+                }
+                Op::Append(slot) => self.append(slot, operands.pop(), span)?,
+                Op::Remove(slot) => {
+                    let index = operands.pop();
+                    let target = self.env.get_mut(slot, span)?;
+                    index::remove(target, &index, span)?;
+                }
+                Op::Push => self.env.push(operands.pop()),
+                Op::Drop => self.visit_pop(span)?,
+                Op::Dup => self.env.dup(span)?,
+                Op::Swap => self.env.swap(span)?,
+                Op::Over => self.env.over(span)?,
+                Op::Rot => self.env.rot(span)?,
+                Op::Clear => self.env.clear(),
+                Op::Pick => {
+                    let n = operands.offset();
+                    self.env.pick(n, span)?;
+                }
+                Op::Print => println!("{}", operands.pop()),
+                Op::Read(slot) => self.visit_read(slot, span)?,
+                Op::ReadInt(slot) => self.visit_readint(slot, span)?,
+                Op::Path => {
+                    let path = self.path(operands.pop(), span)?;
+                    operands.push(Value::Str(path.into()));
+                }
+                Op::WriteFile(append) => {
+                    let text = operands.pop();
+                    match operands.pop() {
+                        Value::Str(path) => write_file(&path, text, append, span)?,
+                        value => unreachable!("`Op::Path` let {:?} through", value),
+                    }
+                }
+                Op::CommentOut(n) => {
+                    let n = n as usize;
+                    let lines = SpanLit(chunk.addresses[n], chunk.addresses[n + 1]);
+                    self.visit_commentop(&lines, span)?;
+                }
+                Op::Uncomment(n) => self.visit_uncomment(var(n), span)?,
+                Op::Copy(n, address) => {
+                    self.visit_copy(var(n), chunk.addresses[address as usize], span)?
+                }
+                Op::Move(n, address) => {
+                    self.visit_move(var(n), chunk.addresses[address as usize], span)?
+                }
+                Op::Call(n) => self.visit_call(var(n), span)?,
+                Op::Include(path, module) => self.visit_include(name(path), module, span)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The stack that expressions are evaluated on.
+/// The compiler makes sure that every op finds the operands it needs.
+struct Operands(Vec<Value>);
+
+impl Operands {
+    fn push(&mut self, value: Value) {
+        self.0.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.0.pop().expect("operand stack underflow")
+    }
+
+    fn top(&self) -> &Value {
+        self.0.last().expect("operand stack underflow")
+    }
+
+    /// Pop the top `n` operands, in the order they were pushed
+    fn split_off(&mut self, n: u32) -> Vec<Value> {
+        let len = self.0.len() - n as usize;
+        self.0.split_off(len)
+    }
+
+    /// Pop an offset that `Op::Offset` has already checked
+    fn offset(&mut self) -> usize {
+        match self.pop() {
+            Value::Int(n) => n as usize,
+            value => unreachable!("{:?} isn't an offset", value),
+        }
+    }
+}
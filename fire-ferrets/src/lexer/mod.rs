@@ -7,14 +7,16 @@ pub use types::*;
 use logos::Logos;
 
 pub struct Lexer<'input> {
-    generated: logos::SpannedIter<'input, LogosToken>,
+    generated: logos::Lexer<'input, LogosToken>,
+    input: &'input str,
     eof: bool,
 }
 
 impl<'input> Lexer<'input> {
     pub fn new(input: &'input str) -> Self {
         Self {
-            generated: LogosToken::lexer(input).spanned(),
+            generated: LogosToken::lexer(input),
+            input,
             eof: false,
         }
     }
@@ -23,10 +25,24 @@ impl<'input> Lexer<'input> {
 impl<'input> Iterator for Lexer<'input> {
     type Item = Token;
 
-    /// Wrapper around `logos::SpannedIter::next` that transforms the span + token kind into our custom `Token` object
+    /// Wrapper around `logos::Lexer::next` that transforms the span + token kind into our custom `Token` object
     fn next(&mut self) -> Option<Self::Item> {
-        match self.generated.next() {
-            Some((token, span)) => Some(Token {
+        let token = self.generated.next();
+        let span = self.generated.span();
+        match token {
+            // the only errors that start with a brace are bad comment literals
+            Some(LogosToken::Error) if self.input[span.clone()].starts_with('{') => {
+                let kind = if self.generated.extras.comment_too_deep {
+                    TokenKind::TooDeepComment
+                } else {
+                    TokenKind::UnclosedComment
+                };
+                Some(Token {
+                    kind,
+                    span: span.into(),
+                })
+            }
+            Some(token) => Some(Token {
                 kind: TokenKind::from(token),
                 span: span.into(),
             }),
@@ -35,7 +51,7 @@ impl<'input> Iterator for Lexer<'input> {
                 self.eof = true;
                 Some(Token {
                     kind: TokenKind::Eof,
-                    span: (0..0).into(),
+                    span: (self.input.len()..self.input.len()).into(),
                 })
             }
         }
@@ -110,10 +126,66 @@ print variable * variable";
             Token {
                 kind: TokenKind::Eof,
                 span: Span {
-                    start: 0_usize,
-                    end: 0_usize
+                    start: 42_usize,
+                    end: 42_usize
                 }
             },
         ]
     )
 }
+
+#[test]
+fn test_float_literals() {
+    let kinds = |input| {
+        Lexer::new(input)
+            .map(|token| token.kind)
+            .collect::<Vec<_>>()
+    };
+    for input in &["1.5", "0.25e3", "2E-4", "1e10"] {
+        assert_eq!(kinds(input), vec![TokenKind::FloatLit, TokenKind::Eof]);
+    }
+    // a line number followed by something else is not a float
+    assert_eq!(
+        kinds("[1:2]"),
+        vec![
+            TokenKind::LeftBracket,
+            TokenKind::IntLit,
+            TokenKind::Colon,
+            TokenKind::IntLit,
+            TokenKind::RightBracket,
+            TokenKind::Eof
+        ]
+    );
+}
+
+#[test]
+fn test_nested_comments() {
+    let tokens = |input| Lexer::new(input).collect::<Vec<_>>();
+    let input = r#"{a {b "}" {c}} d} }"#;
+    let lexed = tokens(input);
+    assert_eq!(lexed.len(), 3);
+    assert_eq!(lexed[0].kind, TokenKind::Comment);
+    assert_eq!(lexed[0].text(input), r#"{a {b "}" {c}} d}"#);
+    assert_eq!(lexed[1].kind, TokenKind::RightBrace);
+
+    let input = "print {a {b}\nprint 1";
+    let kinds: Vec<_> = tokens(input).into_iter().map(|token| token.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![TokenKind::Print, TokenKind::UnclosedComment, TokenKind::Eof]
+    );
+
+    let deep = |depth| format!("{}{}", "{".repeat(depth), "}".repeat(depth));
+    let input = deep(MAX_COMMENT_DEPTH as usize);
+    assert_eq!(tokens(&input)[0].kind, TokenKind::Comment);
+    let input = format!("{} {}", deep(MAX_COMMENT_DEPTH as usize + 1), deep(1));
+    let kinds: Vec<_> = tokens(&input).into_iter().map(|token| token.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::TooDeepComment,
+            TokenKind::Comment,
+            TokenKind::Eof
+        ]
+    );
+}
@@ -6,12 +6,40 @@ use std::fmt::Display;
 
 use logos::Logos;
 
+/// How deep comment literals can be nested in each other.
+/// Each one is parsed by a parser of its own, so without a limit deep nesting overflows the stack.
+pub const MAX_COMMENT_DEPTH: u32 = 100;
+
+/// What the lexer remembers about the tokens it has lexed
+#[derive(Default)]
+pub struct LexerState {
+    /// Whether the last comment literal had others nested too deep in it,
+    /// as opposed to never being closed
+    pub comment_too_deep: bool,
+}
+
 #[derive(Logos, Clone, Debug, PartialEq)]
+#[logos(extras = LexerState)]
 /// An enum of all tokens for logos
 pub enum LogosToken {
+    #[token("append")]
+    Append,
+
+    #[token("appendfile")]
+    AppendFile,
+
+    #[token("as")]
+    As,
+
+    #[token("call")]
+    Call,
+
     #[token("check")]
     Check,
 
+    #[token("include")]
+    Include,
+
     #[token("comment")]
     CommentOp,
 
@@ -27,27 +55,79 @@ pub enum LogosToken {
     #[token("pop")]
     Pop,
 
+    #[token("dup")]
+    Dup,
+
+    #[token("swap")]
+    Swap,
+
+    #[token("over")]
+    Over,
+
+    #[token("rot")]
+    Rot,
+
+    #[token("drop")]
+    Drop,
+
+    #[token("clear")]
+    Clear,
+
+    #[token("pick")]
+    Pick,
+
+    #[token("depth")]
+    Depth,
+
+    #[token("peek")]
+    Peek,
+
+    #[token("stack")]
+    Stack,
+
     #[token("print")]
     Print,
 
     #[token("push")]
     Push,
 
+    #[token("read")]
+    Read,
+
+    #[token("readint")]
+    ReadInt,
+
+    #[token("remove")]
+    Remove,
+
     #[token("set")]
     Set,
 
+    #[token("writefile")]
+    WriteFile,
+
     #[regex(r#"([A-Za-z]|_)([A-Za-z]|_|\d)*"#)]
     Ident,
 
     #[regex("[0-9]+")]
     IntLit,
 
-    #[regex(r#""((\\"|\\\\)|[^\\"])*""#)]
+    #[regex(r"[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?")]
+    #[regex(r"[0-9]+[eE][+-]?[0-9]+")]
+    FloatLit,
+
+    #[regex(r#""(\\.|[^\\"])*""#)]
     StringLit,
 
-    #[regex(r#"\{[^\}]*\}"#)]
+    /// Comment literals can contain other comment literals, so `comment` finds where they end.
+    /// A `{` without a matching `}` makes the rest of the input an error.
+    #[token("{", comment)]
     Comment,
 
+    /// Only ever lexed on its own if it doesn't close a comment literal
+    #[token("}")]
+    RightBrace,
+
     #[token("true")]
     True,
 
@@ -73,6 +153,12 @@ pub enum LogosToken {
     #[token(":")]
     Colon,
 
+    #[token(",")]
+    Comma,
+
+    #[token(".")]
+    Dot,
+
     #[token("[")]
     LeftBracket,
 
@@ -120,24 +206,92 @@ pub enum LogosToken {
     Error,
 }
 
+/// Take the rest of the comment literal whose `{` was just lexed into the token, up to and
+/// including the matching `}`. Braces in nested comment literals are matched too,
+/// those in string literals are ignored. Comment literals nested more than
+/// `MAX_COMMENT_DEPTH` deep are an error, and so are ones that are never closed.
+fn comment(lex: &mut logos::Lexer<LogosToken>) -> bool {
+    let rest = lex.remainder();
+    let mut depth = 1_u32;
+    let mut deepest = depth;
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                // skip to the end of the string, minding escapes
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '{' => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    lex.bump(i + 1);
+                    lex.extras.comment_too_deep = deepest > MAX_COMMENT_DEPTH;
+                    return !lex.extras.comment_too_deep;
+                }
+            }
+            _ => {}
+        }
+    }
+    lex.bump(rest.len());
+    lex.extras.comment_too_deep = false;
+    false
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 /// This is necessary because logos doesn't provide an `#[end]` attribute anymore,
 /// so we'll have to manually map `LogosToken` to `TokenKind`,
 /// and when we receive `None` from the lexer, turn it into the `Eof` variant
 pub enum TokenKind {
+    Append,
+    AppendFile,
+    As,
+    Call,
     Check,
+    Include,
     CommentOp,
     Uncomment,
     Copy,
     Move,
     Pop,
+    Dup,
+    Swap,
+    Over,
+    Rot,
+    Drop,
+    Clear,
+    Pick,
+    Depth,
+    Peek,
+    Stack,
     Print,
     Push,
+    Read,
+    ReadInt,
+    Remove,
     Set,
+    WriteFile,
     Ident,
     IntLit,
+    FloatLit,
     StringLit,
     Comment,
+    RightBrace,
+    /// A `{` that is never closed, along with the rest of the input
+    UnclosedComment,
+    /// A comment literal with others nested more than `MAX_COMMENT_DEPTH` deep in it
+    TooDeepComment,
     True,
     False,
     And,
@@ -146,6 +300,8 @@ pub enum TokenKind {
     Newline,
     Question,
     Colon,
+    Comma,
+    Dot,
     LeftBracket,
     RightBracket,
     LeftParen,
@@ -170,19 +326,42 @@ impl Display for TokenKind {
             f,
             "{}",
             match self {
+                Self::Append => "append",
+                Self::AppendFile => "appendfile",
+                Self::As => "as",
+                Self::Call => "call",
                 Self::Check => "check",
+                Self::Include => "include",
                 Self::CommentOp => "comment operation",
                 Self::Uncomment => "uncomment",
                 Self::Copy => "copy",
                 Self::Move => "move",
                 Self::Pop => "pop",
+                Self::Dup => "dup",
+                Self::Swap => "swap",
+                Self::Over => "over",
+                Self::Rot => "rot",
+                Self::Drop => "drop",
+                Self::Clear => "clear",
+                Self::Pick => "pick",
+                Self::Depth => "depth",
+                Self::Peek => "peek",
+                Self::Stack => "stack",
                 Self::Print => "print",
                 Self::Push => "push",
+                Self::Read => "read",
+                Self::ReadInt => "readint",
+                Self::Remove => "remove",
                 Self::Set => "set",
+                Self::WriteFile => "writefile",
                 Self::Ident => "identifier",
                 Self::IntLit => "integer literal",
+                Self::FloatLit => "float literal",
                 Self::StringLit => "string literal",
                 Self::Comment => "comment literal",
+                Self::RightBrace => "}",
+                Self::UnclosedComment => "unclosed comment literal",
+                Self::TooDeepComment => "comment literal",
                 Self::True => "true",
                 Self::False => "false",
                 Self::And => "and",
@@ -191,6 +370,8 @@ impl Display for TokenKind {
                 Self::Newline => "newline",
                 Self::Question => "question mark",
                 Self::Colon => ":",
+                Self::Comma => ",",
+                Self::Dot => ".",
                 Self::LeftBracket => "[",
                 Self::RightBracket => "]",
                 Self::LeftParen => "(",
@@ -215,19 +396,40 @@ impl Display for TokenKind {
 impl From<LogosToken> for TokenKind {
     fn from(logos_token: LogosToken) -> Self {
         match logos_token {
+            LogosToken::Append => Self::Append,
+            LogosToken::AppendFile => Self::AppendFile,
+            LogosToken::As => Self::As,
+            LogosToken::Call => Self::Call,
             LogosToken::Check => Self::Check,
+            LogosToken::Include => Self::Include,
             LogosToken::CommentOp => Self::CommentOp,
             LogosToken::Uncomment => Self::Uncomment,
             LogosToken::Copy => Self::Copy,
             LogosToken::Move => Self::Move,
             LogosToken::Pop => Self::Pop,
+            LogosToken::Dup => Self::Dup,
+            LogosToken::Swap => Self::Swap,
+            LogosToken::Over => Self::Over,
+            LogosToken::Rot => Self::Rot,
+            LogosToken::Drop => Self::Drop,
+            LogosToken::Clear => Self::Clear,
+            LogosToken::Pick => Self::Pick,
+            LogosToken::Depth => Self::Depth,
+            LogosToken::Peek => Self::Peek,
+            LogosToken::Stack => Self::Stack,
             LogosToken::Print => Self::Print,
             LogosToken::Push => Self::Push,
+            LogosToken::Read => Self::Read,
+            LogosToken::ReadInt => Self::ReadInt,
+            LogosToken::Remove => Self::Remove,
             LogosToken::Set => Self::Set,
+            LogosToken::WriteFile => Self::WriteFile,
             LogosToken::Ident => Self::Ident,
             LogosToken::IntLit => Self::IntLit,
+            LogosToken::FloatLit => Self::FloatLit,
             LogosToken::StringLit => Self::StringLit,
             LogosToken::Comment => Self::Comment,
+            LogosToken::RightBrace => Self::RightBrace,
             LogosToken::True => Self::True,
             LogosToken::False => Self::False,
             LogosToken::And => Self::And,
@@ -236,6 +438,8 @@ impl From<LogosToken> for TokenKind {
             LogosToken::Newline => Self::Newline,
             LogosToken::Question => Self::Question,
             LogosToken::Colon => Self::Colon,
+            LogosToken::Comma => Self::Comma,
+            LogosToken::Dot => Self::Dot,
             LogosToken::LeftBracket => Self::LeftBracket,
             LogosToken::RightBracket => Self::RightBracket,
             LogosToken::LeftParen => Self::LeftParen,
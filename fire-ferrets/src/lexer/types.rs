@@ -23,40 +23,43 @@ impl Token {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
 }
 
 impl Span {
-    /// Return the line number (0 based) and column number (0 based)
-    /// of the token (relative to the input string)
+    /// Return the line number (1 based) and column number (1 based, in characters)
+    /// of the start of the span (relative to the input string)
     pub fn get_line_and_column(&self, input: &str) -> (usize, usize) {
-        let start = self.start as usize;
-        let mut line = 0;
-        let mut column = 0;
-        for (index, byte) in input.bytes().enumerate() {
-            if index == start {
-                break;
-            }
-            if byte == b'\n' {
-                line += 1;
-                column = 0;
-            }
-            column += 1;
-        }
+        let before = &input[..self.start.min(input.len())];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        let line = before.matches('\n').count() + 1;
+        let column = before[line_start..].chars().count() + 1;
         (line, column)
     }
 
     pub fn len(&self) -> usize {
         self.end - self.start
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Return a span that covers both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
 }
 
 impl From<Span> for Range<usize> {
     fn from(span: Span) -> Self {
-        span.start as usize..span.end as usize
+        span.start..span.end
     }
 }
 
@@ -92,3 +95,14 @@ impl fmt::Debug for Token {
         )
     }
 }
+
+#[test]
+fn test_line_and_column() {
+    let input = "set a 1\nprint a\n\nprint \"é\" b";
+    let position = |start| Span { start, end: start }.get_line_and_column(input);
+    assert_eq!(position(0), (1, 1));
+    assert_eq!(position(14), (2, 7));
+    assert_eq!(position(16), (3, 1));
+    assert_eq!(position(17), (4, 1));
+    assert_eq!(position(28), (4, 11));
+}
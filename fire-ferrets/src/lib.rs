@@ -0,0 +1,8 @@
+//! The Ferette language, for the `fire-ferrets` binary and the benchmarks
+
+pub mod diagnostic;
+pub mod error;
+pub mod interpreter;
+pub mod lexer;
+pub mod parser;
+pub mod resolver;
@@ -1,38 +1,164 @@
 use std::{env, fs, process};
 
-use interpreter::Interpreter;
-use parser::{Parser, Stmt};
+use fire_ferrets::{
+    diagnostic::Diagnostic,
+    error::FerError,
+    interpreter::{
+        int::Overflow,
+        program::{Line, Program},
+        Interpreter,
+    },
+    lexer::Span,
+    parser::Parser,
+};
+use repl::Repl;
 
-mod interpreter;
-mod lexer;
-mod parser;
+mod repl;
+
+const USAGE: &str =
+    "usage: fire-ferrets [--overflow=checked|wrapping|saturating|bigint] [--no-fs] [--vm] [file]";
 
 fn main() {
-    let mut args = env::args();
-    args.next();
-    let filename = args.next().unwrap();
-    let contents = fs::read_to_string(filename).unwrap();
-
-    let mut parser = Parser::new(&contents);
-    let mut stmts: Vec<Stmt> = Vec::new();
-    loop {
-        let stmt = parser.parse_stmt();
-        match stmt {
-            Ok(stmt) => stmts.push(stmt),
-            Err(e) => {
-                if e == *"Error: Unexpected EOF" {
-                    break;
-                } else {
-                    eprintln!("{}", e);
-                    process::exit(1);
-                }
+    let mut overflow = Overflow::default();
+    let mut fs_access = true;
+    let mut vm = false;
+    let mut filename = None;
+    for arg in env::args().skip(1) {
+        if arg == "--no-fs" {
+            fs_access = false;
+        } else if arg == "--vm" {
+            vm = true;
+        } else if let Some(mode) = arg.strip_prefix("--overflow=") {
+            overflow = mode.parse().unwrap_or_else(|e| {
+                eprintln!("error: {}\n{}", e, USAGE);
+                process::exit(2);
+            });
+        } else if arg.starts_with("--") || filename.is_some() {
+            eprintln!("error: unexpected argument '{}'\n{}", arg, USAGE);
+            process::exit(2);
+        } else {
+            filename = Some(arg);
+        }
+    }
+
+    let mut interpreter = Interpreter::with_overflow(overflow);
+    interpreter.set_fs_access(fs_access);
+    interpreter.set_vm(vm);
+    match filename {
+        Some(filename) => {
+            let contents = fs::read_to_string(&filename).unwrap_or_else(|e| {
+                eprintln!("error: couldn't read {}: {}", filename, e);
+                process::exit(1);
+            });
+            if !run(&mut interpreter, &filename, &contents) {
+                process::exit(1);
             }
         }
+        None => Repl::new(interpreter).run(),
+    }
+}
+
+/// Parse and run `contents`, printing any errors to stderr.
+/// Returns whether it ran successfully.
+pub fn run(interpreter: &mut Interpreter, filename: &str, contents: &str) -> bool {
+    let (lines, errors) = Parser::new(contents).parse_all();
+    if !errors.is_empty() {
+        for e in &errors {
+            let diagnostic = Diagnostic::from_error(e, contents);
+            eprint!("{}", diagnostic.render(filename, contents));
+        }
+        eprintln!("error: aborting due to {} syntax error(s)", errors.len());
+        return false;
+    }
+    let mut lines: Vec<_> = lines
+        .into_iter()
+        .map(|(stmt, source)| Line::new(stmt, source))
+        .collect();
+    let undefined = interpreter.resolve(&mut lines);
+    for e in &undefined.warnings {
+        let diagnostic = Diagnostic::from_error(e, contents)
+            .into_warning()
+            .with_note("it's only used if the condition before the `?` holds");
+        eprint!("{}", diagnostic.render(filename, contents));
+    }
+    let errors = undefined.errors;
+    if !errors.is_empty() {
+        for e in &errors {
+            eprint!(
+                "{}",
+                Diagnostic::from_error(e, contents).render(filename, contents)
+            );
+        }
+        eprintln!(
+            "error: aborting due to {} undefined variable(s)",
+            errors.len()
+        );
+        return false;
     }
 
-    let mut interpreter = Interpreter::new();
-    match interpreter.run(&stmts) {
-        Ok(u) => u,
-        Err(e) => eprintln!("{}", e),
+    if let Err(e) = interpreter.run_file(Program::new(lines), filename) {
+        eprint!("{}", render_error(interpreter, &e, filename, contents));
+        return false;
+    }
+    true
+}
+
+/// Render a runtime error from `filename`, along with any errors in the files it included
+fn render_error(interpreter: &Interpreter, e: &FerError, filename: &str, contents: &str) -> String {
+    match (e, interpreter.current_line()) {
+        (FerError::Included(_, file, errors), _) => errors
+            .iter()
+            .map(|e| render_error(interpreter, e, &file.name, &file.contents))
+            .collect(),
+        // generated lines don't appear in the input, so show the line on its own
+        (_, Some(line)) if line.generated => {
+            let mut diagnostic = Diagnostic::from_error(e, &line.source)
+                .with_note("this line was inserted by a comment operation");
+            diagnostic.span = span_in_line(e.span(), line);
+            diagnostic.render("<comment>", &line.source)
+        }
+        _ => Diagnostic::from_error(e, contents).render(filename, contents),
+    }
+}
+
+/// Make `span`, which is relative to the code `line` was parsed from, relative to its source.
+/// Spans that aren't inside of the line cover all of it instead.
+fn span_in_line(span: Span, line: &Line) -> Span {
+    let base = line.stmt.span.start;
+    match (span.start.checked_sub(base), span.end.checked_sub(base)) {
+        (Some(start), Some(end)) if end <= line.source.len() => (start..end).into(),
+        _ => (0..line.source.len()).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::span_in_line;
+    use fire_ferrets::{
+        interpreter::program::Line,
+        lexer::Span,
+        parser::{Stmt, StmtKind},
+    };
+
+    #[test]
+    fn test_span_in_line() {
+        // `pop` as the second line of a comment, `{print 1\npop}`
+        let line = Line::generated(Stmt::new(StmtKind::Pop, (8..11).into()), "pop");
+        assert_eq!(
+            span_in_line((8..11).into(), &line),
+            Span { start: 0, end: 3 }
+        );
+        assert_eq!(
+            span_in_line((9..10).into(), &line),
+            Span { start: 1, end: 2 }
+        );
+        assert_eq!(
+            span_in_line((0..1).into(), &line),
+            Span { start: 0, end: 3 }
+        );
+        assert_eq!(
+            span_in_line((8..20).into(), &line),
+            Span { start: 0, end: 3 }
+        );
     }
 }
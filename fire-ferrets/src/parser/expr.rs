@@ -1,8 +1,14 @@
-use super::{Expr, Lit, Parser};
+use std::rc::Rc;
 
-use crate::lexer::TokenKind;
+use super::{Expr, ExprKind, Lit, Parser, Var};
 
-type ExprResult = Result<Expr, String>;
+use crate::{
+    error::{FerError, FerResult},
+    interpreter::value::CommentValue,
+    lexer::{Span, TokenKind},
+};
+
+type ExprResult = FerResult<Expr>;
 
 /// A trait that allows you to get the binding power of the operator `self`
 trait Operator {
@@ -36,24 +42,90 @@ impl Operator for TokenKind {
 
 impl Parser<'_> {
     fn parse_expr(&mut self, binding_power: u8) -> ExprResult {
-        let mut lhs = match self.peek() {
-            TokenKind::Ident | TokenKind::Pop => self.parse_ident()?,
+        let lhs = match self.peek() {
+            TokenKind::Ident => self.parse_ident()?,
+            TokenKind::Pop => Expr::new(ExprKind::Pop, self.next().unwrap().span),
             lit @ TokenKind::IntLit
+            | lit @ TokenKind::FloatLit
             | lit @ TokenKind::StringLit
             | lit @ TokenKind::True
             | lit @ TokenKind::False => self.parse_lit(lit)?,
             TokenKind::LeftParen => self.parse_grouping()?,
+            TokenKind::Depth => {
+                let token = self.next().unwrap();
+                Expr::new(ExprKind::Depth, token.span)
+            }
+            TokenKind::Peek => {
+                let token = self.next().unwrap();
+                Expr::new(ExprKind::Peek, token.span)
+            }
+            TokenKind::Stack => self.parse_stack_index()?,
+            TokenKind::LeftBracket => self.parse_list()?,
+            TokenKind::Comment => self.parse_comment_lit(),
             op @ TokenKind::Minus | op @ TokenKind::Not => self.parse_prefix_op(op)?,
-            TokenKind::Eof => return Err("Error: Unexpected EOF".to_string()),
             _ => {
                 let token = self.next().unwrap();
-                return Err(self.fmt_error(
-                    token.span,
-                    format!("Expected expression, got {}", token.kind),
-                ));
+                return Err(Self::unexpected(token, "expression"));
             }
         };
+        let lhs = self.parse_postfix(lhs)?;
+
+        self.parse_infix(lhs, binding_power)
+    }
+
+    /// Parse any index, slice or field expressions following `expr`
+    pub fn parse_postfix(&mut self, mut expr: Expr) -> ExprResult {
+        loop {
+            let start = expr.span;
+            let expr_box = Box::new(expr);
+            let kind = match self.peek() {
+                TokenKind::LeftBracket => {
+                    self.consume(TokenKind::LeftBracket)?;
+                    let from = self.bound(TokenKind::Colon)?;
+                    let kind = match from {
+                        _ if self.peek() == TokenKind::Colon => {
+                            self.consume(TokenKind::Colon)?;
+                            let to = self.bound(TokenKind::RightBracket)?;
+                            ExprKind::Slice(expr_box, from, to)
+                        }
+                        Some(index) => ExprKind::Index(expr_box, index),
+                        None => {
+                            let token = self.next().unwrap();
+                            return Err(Self::unexpected(token, "index"));
+                        }
+                    };
+                    self.consume(TokenKind::RightBracket)?;
+                    kind
+                }
+                TokenKind::Dot => ExprKind::Index(expr_box, Box::new(self.field()?)),
+                _ => return Ok(*expr_box),
+            };
+            expr = Expr::new(kind, (start.start..self.prev_end).into());
+        }
+    }
+
+    /// Parse `.key`, which is the same as `["key"]`
+    pub fn field(&mut self) -> ExprResult {
+        self.consume(TokenKind::Dot)?;
+        let token = self.next().unwrap();
+        if token.kind != TokenKind::Ident {
+            return Err(Self::unexpected(token, "field name"));
+        }
+        let name = self.text(token).to_string();
+        Ok(Expr::new(ExprKind::Literal(Lit::Str(name)), token.span))
+    }
+
+    /// Parse an optional slice bound, which is left out if the next token is `terminator`
+    fn bound(&mut self, terminator: TokenKind) -> FerResult<Option<Box<Expr>>> {
+        if self.peek() == terminator {
+            Ok(None)
+        } else {
+            Ok(Some(Box::new(self.expr()?)))
+        }
+    }
 
+    /// Parse the binary operators following an already parsed `lhs`
+    pub fn parse_infix(&mut self, mut lhs: Expr, binding_power: u8) -> ExprResult {
         loop {
             let op = match self.peek() {
                 op @ TokenKind::Plus
@@ -69,12 +141,16 @@ impl Parser<'_> {
                 | op @ TokenKind::GreaterEq
                 | op @ TokenKind::NotEq
                 | op @ TokenKind::Equals => op,
-                TokenKind::Eof | TokenKind::RightParen | TokenKind::Newline => break,
+                TokenKind::Eof
+                | TokenKind::RightParen
+                | TokenKind::RightBracket
+                | TokenKind::Colon
+                | TokenKind::Comma
+                | TokenKind::Newline
+                | TokenKind::Question => break,
                 _ => {
                     let token = self.next().unwrap();
-                    return Err(
-                        self.fmt_error(token.span, "Expected operator or terminator".to_string())
-                    );
+                    return Err(Self::unexpected(token, "operator or terminator"));
                 }
             };
 
@@ -85,7 +161,8 @@ impl Parser<'_> {
 
                 self.consume(op)?;
                 let rhs = self.parse_expr(right_binding_power)?;
-                lhs = Expr::BinaryOp(op, Box::new(lhs), Box::new(rhs));
+                let span = lhs.span.to(rhs.span);
+                lhs = Expr::new(ExprKind::BinaryOp(op, Box::new(lhs), Box::new(rhs)), span);
 
                 continue;
             }
@@ -97,49 +174,174 @@ impl Parser<'_> {
     }
 
     fn parse_ident(&mut self) -> ExprResult {
-        let text = {
-            let token = self.next().unwrap();
-            self.text(token)
-        };
+        let token = self.next().unwrap();
+        let text = self.text(token);
 
-        Ok(Expr::Ident(text.to_string()))
+        if self.peek() == TokenKind::LeftParen {
+            return self.parse_builtin_call(text.to_string(), token.span);
+        }
+        let var = Var::new(text.to_string(), token.span);
+        Ok(Expr::new(ExprKind::Ident(var), token.span))
+    }
+
+    /// Parse the parenthesized, comma separated arguments of a builtin function call
+    fn parse_builtin_call(&mut self, name: String, start: Span) -> ExprResult {
+        self.consume(TokenKind::LeftParen)?;
+        let args = self.comma_separated(TokenKind::RightParen)?;
+        let end = self.peek_span();
+        self.consume(TokenKind::RightParen)?;
+        Ok(Expr::new(ExprKind::Call(name, args), start.to(end)))
     }
 
     fn parse_lit(&mut self, lit: TokenKind) -> ExprResult {
         let token = self.next().unwrap();
         let text = self.text(token);
 
-        Ok(match lit {
-            TokenKind::IntLit => Expr::Literal(Lit::Int(text.parse::<i64>().map_err(|_| {
-                self.fmt_error(
+        let lit = match lit {
+            TokenKind::IntLit => Lit::Int(text.parse::<i64>().map_err(|_| {
+                FerError::Parse(
                     token.span,
                     format!("'{}' is not a valid integer literal", text),
                 )
-            })?)),
-            TokenKind::StringLit => Expr::Literal(Lit::Str(text[1..(text.len() - 1)].to_string())),
-            TokenKind::True => Expr::Literal(Lit::Bool(true)),
-            TokenKind::False => Expr::Literal(Lit::Bool(false)),
+            })?),
+            // the lexer only lets through valid floats
+            TokenKind::FloatLit => Lit::Float(text.parse().unwrap()),
+            TokenKind::StringLit => Lit::Str(unescape(&text[1..(text.len() - 1)], token.span)?),
+            TokenKind::True => Lit::Bool(true),
+            TokenKind::False => Lit::Bool(false),
             _ => unreachable!(),
-        })
+        };
+        Ok(Expr::new(ExprKind::Literal(lit), token.span))
+    }
+
+    fn parse_comment_lit(&mut self) -> Expr {
+        let token = self.next().unwrap();
+        let text = self.text(token);
+        let text = &text[1..text.len() - 1];
+        let comment = CommentValue::parsed(text, Self::comment_body(text));
+        let kind = ExprKind::Comment(Rc::new(comment));
+        Expr::new(kind, token.span)
     }
 
     fn parse_prefix_op(&mut self, op: TokenKind) -> ExprResult {
+        let start = self.peek_span();
         self.consume(op)?;
         // unwrapped because it cannot fail, `op` is guaranteed to be either `not` or `-`
         let ((), right_bp) = op.prefix_binding_power().unwrap();
 
-        let expr = Box::new(self.parse_expr(right_bp)?);
-        Ok(Expr::UnaryOp(op, expr))
+        let expr = self.parse_expr(right_bp)?;
+        let span = start.to(expr.span);
+        Ok(Expr::new(ExprKind::UnaryOp(op, Box::new(expr)), span))
+    }
+
+    /// Parse a list or map literal, which one it is depends on whether there's a `:`
+    pub fn parse_list(&mut self) -> ExprResult {
+        let start = self.peek_span();
+        self.consume(TokenKind::LeftBracket)?;
+        let kind = match self.peek() {
+            TokenKind::Colon => {
+                self.consume(TokenKind::Colon)?;
+                ExprKind::Map(vec![])
+            }
+            TokenKind::RightBracket => ExprKind::List(vec![]),
+            _ => {
+                let first = self.expr()?;
+                if self.peek() == TokenKind::Colon {
+                    ExprKind::Map(self.map_entries(first)?)
+                } else {
+                    let mut items = vec![first];
+                    if self.peek() == TokenKind::Comma {
+                        self.consume(TokenKind::Comma)?;
+                        items.extend(self.comma_separated(TokenKind::RightBracket)?);
+                    }
+                    ExprKind::List(items)
+                }
+            }
+        };
+        let end = self.peek_span();
+        self.consume(TokenKind::RightBracket)?;
+        Ok(Expr::new(kind, start.to(end)))
+    }
+
+    /// Parse the `key: value` pairs of a map literal, after its first key
+    fn map_entries(&mut self, first: Expr) -> FerResult<Vec<(Expr, Expr)>> {
+        let mut entries = vec![];
+        let mut key = first;
+        loop {
+            self.consume(TokenKind::Colon)?;
+            entries.push((key, self.expr()?));
+            if self.peek() != TokenKind::Comma {
+                return Ok(entries);
+            }
+            self.consume(TokenKind::Comma)?;
+            key = self.expr()?;
+        }
+    }
+
+    /// Parse comma separated expressions up to, but not including, `terminator`
+    fn comma_separated(&mut self, terminator: TokenKind) -> FerResult<Vec<Expr>> {
+        let mut items = vec![];
+        if self.peek() != terminator {
+            loop {
+                items.push(self.expr()?);
+                if self.peek() != TokenKind::Comma {
+                    break;
+                }
+                self.consume(TokenKind::Comma)?;
+            }
+        }
+        Ok(items)
+    }
+
+    fn parse_stack_index(&mut self) -> ExprResult {
+        let start = self.peek_span();
+        self.consume(TokenKind::Stack)?;
+        self.consume(TokenKind::LeftBracket)?;
+        let index = self.expr()?;
+        let end = self.peek_span();
+        self.consume(TokenKind::RightBracket)?;
+        Ok(Expr::new(
+            ExprKind::StackIndex(Box::new(index)),
+            start.to(end),
+        ))
     }
 
     fn parse_grouping(&mut self) -> ExprResult {
+        let start = self.peek_span();
         self.consume(TokenKind::LeftParen)?;
-        let expr = self.expr();
+        let expr = self.expr()?;
+        let end = self.peek_span();
         self.consume(TokenKind::RightParen)?;
-        expr
+        Ok(Expr::new(expr.kind, start.to(end)))
     }
 
     pub fn expr(&mut self) -> ExprResult {
         self.parse_expr(0)
     }
 }
+
+/// Process the escape sequences in the contents of a string literal
+pub fn unescape(text: &str, span: Span) -> FerResult<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        // the lexer guarantees that a backslash is followed by something
+        out.push(match chars.next().unwrap() {
+            'n' => '\n',
+            't' => '\t',
+            '"' => '"',
+            '\\' => '\\',
+            c => {
+                return Err(FerError::Parse(
+                    span,
+                    format!("Unknown escape sequence \\{}", c),
+                ))
+            }
+        });
+    }
+    Ok(out)
+}
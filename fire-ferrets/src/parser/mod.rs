@@ -1,16 +1,53 @@
 pub mod expr;
 pub mod stmt;
 
-use std::{fmt, iter::Peekable};
+use std::{fmt, iter::Peekable, rc::Rc};
 
-use crate::lexer::{Lexer, Span, Token, TokenKind};
+use crate::{
+    error::{FerError, FerResult},
+    interpreter::value::CommentValue,
+    lexer::{Lexer, Span, Token, TokenKind, MAX_COMMENT_DEPTH},
+    resolver::Slot,
+};
 
 #[derive(Clone, Debug, PartialEq)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
     Literal(Lit),
-    Ident(String),
+    Ident(Var),
+    /// The value on top of the stack, which is removed
+    Pop,
     BinaryOp(TokenKind, Box<Expr>, Box<Expr>),
     UnaryOp(TokenKind, Box<Expr>),
+    /// A call to a builtin function, like `int(x)`
+    Call(String, Vec<Expr>),
+    /// The number of values on the stack
+    Depth,
+    /// The value on top of the stack, without popping it
+    Peek,
+    /// The value this many places below the top of the stack, `stack[0]` is the top
+    StackIndex(Box<Expr>),
+    /// A list literal, `[a, b, c]`
+    List(Vec<Expr>),
+    /// A map literal, `["a": 1, "b": 2]`, or `[:]` when empty
+    Map(Vec<(Expr, Expr)>),
+    /// `xs[i]`
+    Index(Box<Expr>, Box<Expr>),
+    /// `xs[a:b]`, either end can be left out
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+    /// A comment literal, `{print 1}`, shared with every value it evaluates to
+    Comment(Rc<CommentValue>),
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
 }
 
 impl fmt::Display for Expr {
@@ -18,34 +55,140 @@ impl fmt::Display for Expr {
         write!(
             f,
             "{}",
-            match self {
-                Expr::Literal(l) => l.to_string(),
-                Expr::Ident(i) => i.to_string(),
-                Expr::BinaryOp(op, lhs, rhs) => format!("({} {} {})", op, lhs, rhs),
-                Expr::UnaryOp(op, expr) => format!("({} {})", op, expr),
+            match &self.kind {
+                ExprKind::Literal(l) => l.to_string(),
+                ExprKind::Ident(var) => var.to_string(),
+                ExprKind::Pop => "pop".to_string(),
+                ExprKind::BinaryOp(op, lhs, rhs) => format!("({} {} {})", op, lhs, rhs),
+                ExprKind::UnaryOp(op, expr) => format!("({} {})", op, expr),
+                ExprKind::Depth => "depth".to_string(),
+                ExprKind::Peek => "peek".to_string(),
+                ExprKind::StackIndex(index) => format!("(stack {})", index),
+                ExprKind::List(items) => {
+                    let items: Vec<_> = items.iter().map(Expr::to_string).collect();
+                    format!("[{}]", items.join(" "))
+                }
+                ExprKind::Map(entries) => {
+                    let entries: Vec<_> = entries
+                        .iter()
+                        .map(|(key, value)| format!("{}: {}", key, value))
+                        .collect();
+                    format!("[{}]", entries.join(" "))
+                }
+                ExprKind::Index(expr, index) => format!("(index {} {})", expr, index),
+                ExprKind::Slice(expr, start, end) => {
+                    let bound = |bound: &Option<Box<Expr>>| match bound {
+                        Some(bound) => bound.to_string(),
+                        None => "_".to_string(),
+                    };
+                    format!("(slice {} {} {})", expr, bound(start), bound(end))
+                }
+                ExprKind::Call(name, args) => {
+                    let args: Vec<_> = args.iter().map(Expr::to_string).collect();
+                    format!("({} {})", name, args.join(" "))
+                }
+                ExprKind::Comment(comment) => comment.body().to_string(),
             }
         )
     }
 }
 
+/// A variable, which the resolver gives a slot in the environment before it's used.
+/// Comment operations may name a comment in a module, like `math.square`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Var {
+    pub name: String,
+    pub span: Span,
+    pub slot: Option<Slot>,
+}
+
+impl Var {
+    pub fn new(name: String, span: Span) -> Self {
+        Self {
+            name,
+            span,
+            slot: None,
+        }
+    }
+
+    /// The name of the variable itself, without any module members
+    pub fn root(&self) -> &str {
+        // `split` always returns at least one part
+        self.name.split('.').next().unwrap()
+    }
+
+    /// The slot of the variable, once it's been resolved
+    pub fn slot(&self) -> Slot {
+        self.slot
+            .expect("variables are resolved before they're used")
+    }
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
-pub enum Stmt {
-    Set(String, Expr),
+pub struct Stmt {
+    pub kind: StmtKind,
+    /// The span of the statement, not including the newline ending it
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StmtKind {
+    Set(Var, Expr),
+    /// Assign to an element of a variable, `set xs[0].name x` has the path `[0, "name"]`
+    SetIndex(Var, Vec<Expr>, Expr),
+    Append(Var, Expr),
+    Remove(Var, Expr),
     Push(Expr),
     Check(Expr),
     Pop,
+    Dup,
+    Swap,
+    Over,
+    Rot,
+    Drop,
+    Clear,
+    /// Copy the value this many places below the top of the stack onto the top
+    Pick(Expr),
     Print(Expr),
+    /// Read a line of input into a variable
+    Read(Var),
+    /// Read a line of input and parse it as an integer
+    ReadInt(Var),
+    /// Write the second expression to the file at the first, replacing what was there
+    WriteFile(Expr, Expr),
+    /// Write the second expression to the end of the file at the first
+    AppendFile(Expr, Expr),
     CommentOp(SpanLit),
-    Uncomment(String),
-    Copy(String, HalfSpanLit),
-    Move(String, HalfSpanLit),
-    Comment(Comment),
+    Uncomment(Var),
+    Copy(Var, HalfSpanLit),
+    Move(Var, HalfSpanLit),
+    Call(Var),
+    /// Run another file, in a fresh environment if it's given a module name
+    Include(String, Option<Var>),
+    /// A comment operation that only runs if the guard is truthy.
+    /// Without a guard expression, the condition is popped off the stack.
+    Conditional(Option<Expr>, Box<Stmt>),
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
 }
 
+/// The code in a comment, parsed along with the program the comment is in
 #[derive(Clone, Debug, PartialEq)]
 pub enum Comment {
-    Valid(Vec<Stmt>),
-    Invalid,
+    /// Every statement along with its source text
+    Valid(Vec<(Stmt, String)>),
+    /// The first error in the comment
+    Invalid(FerError),
     Empty,
 }
 
@@ -54,33 +197,65 @@ impl fmt::Display for Stmt {
         write!(
             f,
             "{}",
-            match self {
-                Stmt::Set(ident, expr) => format!("(set {} {})", ident, expr),
-                Stmt::Push(expr) => format!("(push {})", expr),
-                Stmt::Check(expr) => format!("(check {}", expr),
-                Stmt::Pop => "(pop)".to_string(),
-                Stmt::Print(expr) => format!("(print {})", expr),
-                Stmt::CommentOp(spanlit) => format!("(comment {})", spanlit),
-                Stmt::Uncomment(ident) => format!("(uncomment {})", ident),
-                Stmt::Copy(ident, halfspanlit) => format!("(copy {} {})", ident, halfspanlit),
-                Stmt::Move(ident, halfspanlit) => format!("(move {} {})", ident, halfspanlit),
-                Stmt::Comment(Comment::Valid(stmts)) => {
-                    let mut buf = "[".to_string();
-                    for stmt in stmts {
-                        buf.push_str(&format!("{}, ", stmt));
-                    }
-                    buf.push(']');
-                    format!("(commentlit ({}))", buf)
+            match &self.kind {
+                StmtKind::Set(ident, expr) => format!("(set {} {})", ident, expr),
+                StmtKind::SetIndex(ident, path, expr) => {
+                    let path: Vec<_> = path.iter().map(|index| format!("[{}]", index)).collect();
+                    format!("(set {}{} {})", ident, path.join(""), expr)
+                }
+                StmtKind::Append(ident, expr) => format!("(append {} {})", ident, expr),
+                StmtKind::Remove(ident, expr) => format!("(remove {} {})", ident, expr),
+                StmtKind::Push(expr) => format!("(push {})", expr),
+                StmtKind::Check(expr) => format!("(check {})", expr),
+                StmtKind::Pop => "(pop)".to_string(),
+                StmtKind::Dup => "(dup)".to_string(),
+                StmtKind::Swap => "(swap)".to_string(),
+                StmtKind::Over => "(over)".to_string(),
+                StmtKind::Rot => "(rot)".to_string(),
+                StmtKind::Drop => "(drop)".to_string(),
+                StmtKind::Clear => "(clear)".to_string(),
+                StmtKind::Pick(expr) => format!("(pick {})", expr),
+                StmtKind::Print(expr) => format!("(print {})", expr),
+                StmtKind::Read(ident) => format!("(read {})", ident),
+                StmtKind::ReadInt(ident) => format!("(readint {})", ident),
+                StmtKind::WriteFile(path, text) => format!("(writefile {} {})", path, text),
+                StmtKind::AppendFile(path, text) => format!("(appendfile {} {})", path, text),
+                StmtKind::CommentOp(spanlit) => format!("(comment {})", spanlit),
+                StmtKind::Uncomment(ident) => format!("(uncomment {})", ident),
+                StmtKind::Copy(ident, halfspanlit) => format!("(copy {} {})", ident, halfspanlit),
+                StmtKind::Move(ident, halfspanlit) => format!("(move {} {})", ident, halfspanlit),
+                StmtKind::Call(ident) => format!("(call {})", ident),
+                StmtKind::Include(path, None) => format!("(include {:?})", path),
+                StmtKind::Include(path, Some(module)) => {
+                    format!("(include {:?} {})", path, module)
                 }
-                Stmt::Comment(Comment::Invalid | Comment::Empty) => format!("(commentlit)"),
+                StmtKind::Conditional(Some(guard), stmt) => format!("(? {} {})", guard, stmt),
+                StmtKind::Conditional(None, stmt) => format!("(? {})", stmt),
             }
         )
     }
 }
 
+impl fmt::Display for Comment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comment::Valid(stmts) => {
+                let mut buf = "[".to_string();
+                for (stmt, _) in stmts {
+                    buf.push_str(&format!("{}, ", stmt));
+                }
+                buf.push(']');
+                write!(f, "(commentlit ({}))", buf)
+            }
+            Comment::Invalid(_) | Comment::Empty => write!(f, "(commentlit)"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Lit {
     Int(i64),
+    Float(f64),
     Str(String),
     Bool(bool),
 }
@@ -107,7 +282,7 @@ impl fmt::Display for NumKind {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
-pub struct HalfSpanLit(NumKind, usize);
+pub struct HalfSpanLit(pub NumKind, pub usize);
 
 impl fmt::Display for HalfSpanLit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -120,7 +295,8 @@ pub struct SpanLit(pub HalfSpanLit, pub HalfSpanLit);
 
 impl fmt::Display for SpanLit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{} {}]", self.0, self.1)
+        let SpanLit(HalfSpanLit(k1, n1), HalfSpanLit(k2, n2)) = self;
+        write!(f, "[{}{}:{}{}]", k1, n1, k2, n2)
     }
 }
 
@@ -131,6 +307,7 @@ impl fmt::Display for Lit {
             "{}",
             match self {
                 Self::Int(i) => i.to_string(),
+                Self::Float(x) => format!("{:?}", x),
                 Self::Str(s) => s.to_string(),
                 Self::Bool(b) => b.to_string(),
             }
@@ -144,6 +321,10 @@ pub struct Parser<'input> {
     input: &'input str,
     /// The lexer wrapped in `Peekable`
     tokens: Peekable<Lexer<'input>>,
+    /// Where the last consumed token ended, for slicing out statement source text
+    prev_end: usize,
+    /// Whether the last consumed token was a newline, for error recovery
+    at_line_start: bool,
 }
 
 impl<'input> Parser<'input> {
@@ -151,6 +332,8 @@ impl<'input> Parser<'input> {
         Self {
             input,
             tokens: Lexer::new(input).peekable(),
+            prev_end: 0,
+            at_line_start: true,
         }
     }
 
@@ -167,32 +350,253 @@ impl<'input> Parser<'input> {
             .unwrap_or(TokenKind::Eof)
     }
 
+    /// Look ahead to the span of the next token without consuming it
+    pub fn peek_span(&mut self) -> Span {
+        self.tokens
+            .peek()
+            .map(|token| token.span)
+            .unwrap_or_else(|| (self.input.len()..self.input.len()).into())
+    }
+
+    /// Skip any blank lines and check whether the input has been exhausted
+    pub fn at_eof(&mut self) -> bool {
+        while self.peek() == TokenKind::Newline {
+            self.next();
+        }
+        self.peek() == TokenKind::Eof
+    }
+
     /// Peek ahead to the next token and check if its `TokenKind` is `kind`
     pub fn _at(&mut self, kind: TokenKind) -> bool {
         self.peek() == kind
     }
 
     /// Consume and return the next token
-    pub fn next(&mut self) -> Option<Token> {
-        self.tokens.next()
+    pub(crate) fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.next();
+        if let Some(Token { kind, span }) = token {
+            if kind != TokenKind::Eof {
+                self.prev_end = span.end;
+                self.at_line_start = kind == TokenKind::Newline;
+            }
+        }
+        token
     }
 
     /// Consume token and check that it's `TokenKind` is as `expected`
-    pub fn consume(&mut self, expected: TokenKind) -> Result<(), String> {
+    pub fn consume(&mut self, expected: TokenKind) -> FerResult<()> {
         let token = self.next().unwrap();
         if token.kind != expected {
-            Err(self.fmt_error(
-                token.span,
-                format!("Expected {}, got {}", expected, token.kind),
-            ))
+            Err(Self::unexpected(token, &expected.to_string()))
         } else {
             Ok(())
         }
     }
 
-    /// Format error with line, column and message
-    pub fn fmt_error(&self, span: Span, msg: String) -> String {
-        let (line, column) = span.get_line_and_column(self.input);
-        format!("Error at {}:{} = {}", line, column, msg)
+    /// Create an error for when `token` isn't what we `expected`
+    pub fn unexpected(token: Token, expected: &str) -> FerError {
+        match token.kind {
+            TokenKind::Error => FerError::Lex(token.span),
+            TokenKind::UnclosedComment => FerError::Parse(
+                // the rest of the input is part of the token, only point at the brace
+                Span {
+                    start: token.span.start,
+                    end: token.span.start + 1,
+                },
+                "This { is never closed, comment literals need a matching }".to_string(),
+            ),
+            TokenKind::TooDeepComment => FerError::Parse(
+                Span {
+                    start: token.span.start,
+                    end: token.span.start + 1,
+                },
+                format!(
+                    "Comment literals can't be nested more than {} deep",
+                    MAX_COMMENT_DEPTH
+                ),
+            ),
+            TokenKind::RightBrace => FerError::Parse(
+                token.span,
+                "This } doesn't close any comment literal".to_string(),
+            ),
+            kind => FerError::Parse(token.span, format!("Expected {}, got {}", expected, kind)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExprKind, Parser, StmtKind};
+    use crate::{
+        error::FerError,
+        lexer::{Span, MAX_COMMENT_DEPTH},
+    };
+
+    #[test]
+    fn test_stmt_and_expr_spans() {
+        let stmt = Parser::new("print 1 + x\n").parse_stmt().unwrap();
+        assert_eq!(stmt.span, Span { start: 0, end: 11 });
+        if let StmtKind::Print(expr) = stmt.kind {
+            assert_eq!(expr.span, Span { start: 6, end: 11 });
+            if let ExprKind::BinaryOp(_, lhs, rhs) = expr.kind {
+                assert_eq!(lhs.span, Span { start: 6, end: 7 });
+                assert_eq!(rhs.span, Span { start: 10, end: 11 });
+                return;
+            }
+        }
+        panic!("expected a print statement with a binary expression");
+    }
+
+    #[test]
+    fn test_builtin_call() {
+        let stmt = Parser::new("print int(1.5, x) + 1").parse_stmt().unwrap();
+        assert_eq!(stmt.to_string(), "(print (+ (int 1.5 x) 1))");
+        if let StmtKind::Print(expr) = stmt.kind {
+            if let ExprKind::BinaryOp(_, call, _) = expr.kind {
+                assert_eq!(call.span, Span { start: 6, end: 17 });
+                return;
+            }
+        }
+        panic!("expected a print statement with a binary expression");
+    }
+
+    #[test]
+    fn test_stack_access() {
+        let stmt = Parser::new("check stack[depth - 1] < peek")
+            .parse_stmt()
+            .unwrap();
+        assert_eq!(stmt.to_string(), "(check (< (stack (- depth 1)) peek))");
+        if let StmtKind::Check(expr) = stmt.kind {
+            if let ExprKind::BinaryOp(_, index, _) = expr.kind {
+                assert_eq!(index.span, Span { start: 6, end: 22 });
+                return;
+            }
+        }
+        panic!("expected a check statement with a binary expression");
+    }
+
+    #[test]
+    fn test_lists() {
+        let parse = |input| Parser::new(input).parse_stmt().unwrap().to_string();
+        assert_eq!(parse("print [1, x][0]"), "(print (index [1 x] 0))");
+        assert_eq!(
+            parse("print xs[1:][:-1]"),
+            "(print (slice (slice xs 1 _) _ (- 1)))"
+        );
+        assert_eq!(parse("set xs[0][i] []"), "(set xs[0][i] [])");
+        // without an index right after the name, it's a list being assigned
+        assert_eq!(parse("set xs [0]"), "(set xs [0])");
+        assert_eq!(parse("set xs[0] 5"), "(set xs[0] 5)");
+        assert_eq!(parse("set xs [0, 1][i]"), "(set xs (index [0 1] i))");
+        // but a list of one element followed by more looks like an element was meant
+        let inputs = [
+            ("set xs [0] 5", 10),
+            ("set xs [0] [5]", 10),
+            ("set xs [i] + ys", 10),
+        ];
+        for &(input, end) in &inputs {
+            assert_eq!(
+                Parser::new(input).parse_stmt(),
+                Err(FerError::Parse(
+                    Span { start: 7, end },
+                    "To assign to an element of xs, write `set xs[...]` without a space, \
+                     `set xs [...]` assigns a list"
+                        .to_string()
+                ))
+            );
+        }
+        assert!(Parser::new("print xs[]").parse_stmt().is_err());
+    }
+
+    #[test]
+    fn test_maps() {
+        let parse = |input| Parser::new(input).parse_stmt().unwrap().to_string();
+        assert_eq!(parse(r#"print ["a": 1, k: [:]]"#), "(print [a: 1 k: []])");
+        assert_eq!(
+            parse("print m.a[0].b"),
+            "(print (index (index (index m a) 0) b))"
+        );
+        assert_eq!(parse("set m.a[0] 1"), "(set m[a][0] 1)");
+        assert!(Parser::new(r#"print ["a": 1, 2]"#).parse_stmt().is_err());
+        assert!(Parser::new("print m.1").parse_stmt().is_err());
+    }
+
+    #[test]
+    fn test_comment_exprs() {
+        let parse = |input| Parser::new(input).parse_stmt().unwrap().to_string();
+        assert_eq!(
+            parse("set f {print 1}"),
+            "(set f (commentlit ([(print 1), ])))"
+        );
+        assert_eq!(parse("push [{}]"), "(push [(commentlit)])");
+        assert_eq!(
+            parse("{pop} == f ? call f"),
+            "(? (== (commentlit ([(pop), ])) f) (call f))"
+        );
+        assert_eq!(parse("{pop}"), "(push (commentlit ([(pop), ])))");
+    }
+
+    #[test]
+    fn test_comment_depth() {
+        let deep = |depth| format!("push {}{}", "{".repeat(depth), "}".repeat(depth));
+        assert!(Parser::new(&deep(MAX_COMMENT_DEPTH as usize))
+            .parse_stmt()
+            .is_ok());
+        // far deeper than the stack would allow without a limit
+        assert_eq!(
+            Parser::new(&deep(5000)).parse_stmt(),
+            Err(FerError::Parse(
+                Span { start: 5, end: 6 },
+                format!(
+                    "Comment literals can't be nested more than {} deep",
+                    MAX_COMMENT_DEPTH
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Parser::new("set 1 2").parse_stmt(),
+            Err(FerError::Parse(
+                Span { start: 4, end: 5 },
+                "Expected identifier, got integer literal".to_string()
+            ))
+        );
+        assert_eq!(
+            Parser::new("print $").parse_stmt(),
+            Err(FerError::Lex(Span { start: 6, end: 7 }))
+        );
+        // only comment operations can follow a `?`, a comment literal can't
+        assert_eq!(
+            Parser::new("x ? {print 1}").parse_stmt(),
+            Err(FerError::Parse(
+                Span { start: 4, end: 13 },
+                "Expected comment operation, got comment literal".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_recovery() {
+        let input = "print 1\nset 2 3\nprint (1\npush 2 2\npop\nprint 1 +\nprint 3";
+        let (lines, errors) = Parser::new(input).parse_all();
+        let sources: Vec<_> = lines.iter().map(|(_, source)| *source).collect();
+        assert_eq!(sources, vec!["print 1", "pop", "print 3"]);
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors[3].span(), Span { start: 47, end: 48 });
+    }
+
+    #[test]
+    fn test_include() {
+        let parse = |input| Parser::new(input).parse_stmt().unwrap().to_string();
+        assert_eq!(parse(r#"include "lib/a.fer""#), r#"(include "lib/a.fer")"#);
+        assert_eq!(
+            parse(r#"include "a\tb.fer" as a"#),
+            r#"(include "a\tb.fer" a)"#
+        );
+        assert_eq!(parse("call a.b.c"), "(call a.b.c)");
+        assert!(Parser::new("include a").parse_stmt().is_err());
     }
 }
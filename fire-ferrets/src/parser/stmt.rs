@@ -1,90 +1,181 @@
-use crate::lexer::TokenKind;
+use crate::{
+    error::{FerError, FerResult},
+    lexer::TokenKind,
+};
 
-use super::{Comment, HalfSpanLit, NumKind, Parser, SpanLit, Stmt};
+use super::{
+    expr::unescape, Comment, Expr, ExprKind, HalfSpanLit, NumKind, Parser, SpanLit, Stmt, StmtKind,
+    Var,
+};
 
-type StmtResult = Result<Stmt, String>;
+type StmtResult = FerResult<StmtKind>;
 
-impl Parser<'_> {
-    pub fn parse_stmt(&mut self) -> StmtResult {
+impl<'input> Parser<'input> {
+    pub fn parse_stmt(&mut self) -> FerResult<Stmt> {
+        let stmt = self.spanned(Self::parse_stmt_kind)?;
+        self.end_of_stmt()?;
+        Ok(stmt)
+    }
+
+    fn parse_stmt_kind(&mut self) -> StmtResult {
         match self.peek() {
             TokenKind::Set => self.parse_set(),
             TokenKind::Push => self.parse_push(),
+            TokenKind::Append => self.parse_append(),
+            TokenKind::Remove => self.parse_remove(),
             TokenKind::Check => self.parse_check(),
             TokenKind::Pop => self.parse_pop(),
+            TokenKind::Dup
+            | TokenKind::Swap
+            | TokenKind::Over
+            | TokenKind::Rot
+            | TokenKind::Drop
+            | TokenKind::Clear => self.parse_stack_op(),
+            TokenKind::Pick => self.parse_pick(),
             TokenKind::Print => self.parse_print(),
+            TokenKind::Read | TokenKind::ReadInt => self.parse_read(),
+            TokenKind::WriteFile | TokenKind::AppendFile => self.parse_write(),
             TokenKind::CommentOp => self.parse_commentop(),
             TokenKind::Uncomment => self.parse_uncomment(),
             TokenKind::Copy => self.parse_copy(),
             TokenKind::Move => self.parse_move(),
             TokenKind::Comment => self.parse_comment(),
-            TokenKind::Eof => Err("Error: Unexpected EOF".to_string()),
+            TokenKind::Call => self.parse_call(),
+            TokenKind::Include => self.parse_include(),
+            TokenKind::Question => self.parse_conditional(None),
+            TokenKind::Ident
+            | TokenKind::IntLit
+            | TokenKind::FloatLit
+            | TokenKind::StringLit
+            | TokenKind::True
+            | TokenKind::False
+            | TokenKind::Depth
+            | TokenKind::Peek
+            | TokenKind::Stack
+            | TokenKind::LeftBracket
+            | TokenKind::LeftParen
+            | TokenKind::Minus
+            | TokenKind::Not => {
+                let guard = self.expr()?;
+                self.parse_conditional(Some(guard))
+            }
             _ => {
                 let token = self.next().unwrap();
-                Err(self.fmt_error(
-                    token.span,
-                    format!("Expected statement, got {}", token.kind),
-                ))
+                Err(Self::unexpected(token, "statement"))
             }
         }
     }
 
-    fn ident(&mut self) -> Result<String, String> {
+    /// Parse a single statement and return it along with its source text
+    pub fn parse_line(&mut self) -> FerResult<(Stmt, &'input str)> {
+        self.at_eof();
+        let stmt = self.parse_stmt()?;
+        let source = &self.input[stmt.span];
+        Ok((stmt, source))
+    }
+
+    /// Parse the whole input, recovering from syntax errors by skipping to the next line.
+    /// Returns every statement that could be parsed along with its source text,
+    /// and every error encountered along the way.
+    pub fn parse_all(&mut self) -> (Vec<(Stmt, &'input str)>, Vec<FerError>) {
+        let mut lines = vec![];
+        let mut errors = vec![];
+        while !self.at_eof() {
+            match self.parse_line() {
+                Ok(line) => lines.push(line),
+                Err(e) => {
+                    errors.push(e);
+                    self.synchronize();
+                }
+            }
+        }
+        (lines, errors)
+    }
+
+    /// Skip the rest of the current line after an error
+    fn synchronize(&mut self) {
+        // the error might have been caused by the newline itself
+        while !self.at_line_start {
+            match self.peek() {
+                TokenKind::Eof => break,
+                _ => {
+                    self.next();
+                }
+            }
+        }
+    }
+
+    /// Run `parse` and attach the span of everything it consumed to its result
+    fn spanned(&mut self, parse: impl FnOnce(&mut Self) -> StmtResult) -> FerResult<Stmt> {
+        let start = self.peek_span().start;
+        let kind = parse(self)?;
+        Ok(Stmt::new(kind, (start..self.prev_end).into()))
+    }
+
+    /// Statements end with a newline, unless they're the last one in the input
+    fn end_of_stmt(&mut self) -> FerResult<()> {
+        if self.peek() == TokenKind::Eof {
+            Ok(())
+        } else {
+            self.consume(TokenKind::Newline)
+        }
+    }
+
+    fn ident(&mut self) -> FerResult<String> {
         let ident = self.next().unwrap();
         if let TokenKind::Ident = ident.kind {
             Ok(self.text(ident).to_string())
         } else {
-            Err(self.fmt_error(
-                ident.span,
-                format!("Expected identifier, got {}", ident.kind),
-            ))
+            Err(Self::unexpected(ident, "identifier"))
         }
     }
 
-    fn span_num(&mut self) -> Result<HalfSpanLit, String> {
+    fn var(&mut self) -> FerResult<Var> {
+        let start = self.peek_span().start;
+        let name = self.ident()?;
+        Ok(Var::new(name, (start..self.prev_end).into()))
+    }
+
+    /// A variable, or a member of a module like `math.square`
+    fn dotted_var(&mut self) -> FerResult<Var> {
+        let mut var = self.var()?;
+        while self.peek() == TokenKind::Dot {
+            self.consume(TokenKind::Dot)?;
+            var.name.push('.');
+            var.name.push_str(&self.ident()?);
+        }
+        var.span.end = self.prev_end;
+        Ok(var)
+    }
+
+    fn span_num(&mut self) -> FerResult<HalfSpanLit> {
         let token = self.next().unwrap();
 
-        match token.kind {
-            TokenKind::Minus => {
-                let num = self.next().unwrap();
-                if num.kind != TokenKind::IntLit {
-                    return Err(self.fmt_error(
-                        num.span,
-                        format!("Expected integer literal, got {}", token.kind),
-                    ));
-                }
-                let text = self.text(num);
-                Ok(HalfSpanLit(NumKind::Neg, text.parse().unwrap()))
-            }
-            TokenKind::Plus => {
-                let num = self.next().unwrap();
-                if num.kind != TokenKind::IntLit {
-                    return Err(self.fmt_error(
-                        num.span,
-                        format!("Expected integer literal, got {}", token.kind),
-                    ));
-                }
-                let text = self.text(num);
-                Ok(HalfSpanLit(NumKind::Pos, text.parse().unwrap()))
-            }
-            TokenKind::IntLit => {
-                let text = self.text(token);
-                Ok(HalfSpanLit(NumKind::Abs, text.parse().unwrap()))
-            }
-            _ => Err(self.fmt_error(
-                token.span,
-                format!("Expected +, - or integer literal, got {}", token.kind),
-            )),
+        let (kind, num) = match token.kind {
+            TokenKind::Minus => (NumKind::Neg, self.next().unwrap()),
+            TokenKind::Plus => (NumKind::Pos, self.next().unwrap()),
+            TokenKind::IntLit => (NumKind::Abs, token),
+            _ => return Err(Self::unexpected(token, "+, - or integer literal")),
+        };
+        if num.kind != TokenKind::IntLit {
+            return Err(Self::unexpected(num, "integer literal"));
         }
+
+        let text = self.text(num);
+        let n = text.parse().map_err(|_| {
+            FerError::Parse(num.span, format!("'{}' is not a valid line number", text))
+        })?;
+        Ok(HalfSpanLit(kind, n))
     }
 
-    fn halfspan(&mut self) -> Result<HalfSpanLit, String> {
+    fn halfspan(&mut self) -> FerResult<HalfSpanLit> {
         self.consume(TokenKind::LeftBracket)?;
         let num = self.span_num()?;
         self.consume(TokenKind::RightBracket)?;
         Ok(num)
     }
 
-    fn span(&mut self) -> Result<SpanLit, String> {
+    fn span(&mut self) -> FerResult<SpanLit> {
         self.consume(TokenKind::LeftBracket)?;
         let num1 = self.span_num()?;
         self.consume(TokenKind::Colon)?;
@@ -97,94 +188,226 @@ impl Parser<'_> {
     fn parse_set(&mut self) -> StmtResult {
         self.next().unwrap();
 
-        let text = self.ident()?;
+        let var = self.var()?;
+        // `set xs[0] x` assigns to an element, but `set xs [0]` assigns a list
+        let spaced =
+            self.peek() == TokenKind::LeftBracket && self.peek_span().start != self.prev_end;
+        let mut path = vec![];
+        loop {
+            match self.peek() {
+                TokenKind::LeftBracket if self.peek_span().start == self.prev_end => {
+                    self.consume(TokenKind::LeftBracket)?;
+                    path.push(self.expr()?);
+                    self.consume(TokenKind::RightBracket)?;
+                }
+                TokenKind::Dot => path.push(self.field()?),
+                _ => break,
+            }
+        }
+        let expr = if spaced {
+            self.spaced_list_value(&var)?
+        } else {
+            self.expr()?
+        };
+        if path.is_empty() {
+            Ok(StmtKind::Set(var, expr))
+        } else {
+            Ok(StmtKind::SetIndex(var, path, expr))
+        }
+    }
+
+    /// Parse the value of `set xs [...]`, which is an expression starting with a list literal.
+    /// A list of one element has to be all of it, since `set xs [i] x` looks like it was meant
+    /// to assign to an element of `xs`.
+    fn spaced_list_value(&mut self, var: &Var) -> FerResult<Expr> {
+        let list = self.parse_list()?;
+        let single = matches!(&list.kind, ExprKind::List(items) if items.len() == 1);
+        if single && !matches!(self.peek(), TokenKind::Newline | TokenKind::Eof) {
+            return Err(FerError::Parse(
+                list.span,
+                format!(
+                    "To assign to an element of {0}, write `set {0}[...]` without a space, \
+                     `set {0} [...]` assigns a list",
+                    var
+                ),
+            ));
+        }
+        let expr = self.parse_postfix(list)?;
+        self.parse_infix(expr, 0)
+    }
+
+    fn parse_append(&mut self) -> StmtResult {
+        self.next().unwrap();
+        let ident = self.var()?;
+        let expr = self.expr()?;
+        Ok(StmtKind::Append(ident, expr))
+    }
+
+    fn parse_remove(&mut self) -> StmtResult {
+        self.next().unwrap();
+        let ident = self.var()?;
         let expr = self.expr()?;
-        self.consume(TokenKind::Newline)?;
-        Ok(Stmt::Set(text.to_string(), expr))
+        Ok(StmtKind::Remove(ident, expr))
     }
 
     fn parse_push(&mut self) -> StmtResult {
         self.next().unwrap();
         let expr = self.expr()?;
-        self.consume(TokenKind::Newline)?;
-        Ok(Stmt::Push(expr))
+        Ok(StmtKind::Push(expr))
     }
 
     fn parse_pop(&mut self) -> StmtResult {
+        let token = self.next().unwrap();
+        if let TokenKind::Newline | TokenKind::Eof = self.peek() {
+            Ok(StmtKind::Pop)
+        } else {
+            // `pop` is the start of a guard expression
+            let pop = Expr::new(ExprKind::Pop, token.span);
+            let guard = self.parse_infix(pop, 0)?;
+            self.parse_conditional(Some(guard))
+        }
+    }
+
+    fn parse_stack_op(&mut self) -> StmtResult {
+        Ok(match self.next().unwrap().kind {
+            TokenKind::Dup => StmtKind::Dup,
+            TokenKind::Swap => StmtKind::Swap,
+            TokenKind::Over => StmtKind::Over,
+            TokenKind::Rot => StmtKind::Rot,
+            TokenKind::Drop => StmtKind::Drop,
+            TokenKind::Clear => StmtKind::Clear,
+            _ => unreachable!(),
+        })
+    }
+
+    fn parse_pick(&mut self) -> StmtResult {
         self.next().unwrap();
-        self.consume(TokenKind::Newline)?;
-        Ok(Stmt::Pop)
+        let expr = self.expr()?;
+        Ok(StmtKind::Pick(expr))
     }
 
     fn parse_print(&mut self) -> StmtResult {
         self.next().unwrap();
         let expr = self.expr()?;
-        self.consume(TokenKind::Newline)?;
-        Ok(Stmt::Print(expr))
+        Ok(StmtKind::Print(expr))
+    }
+
+    fn parse_read(&mut self) -> StmtResult {
+        let token = self.next().unwrap();
+        let ident = self.var()?;
+        Ok(match token.kind {
+            TokenKind::Read => StmtKind::Read(ident),
+            _ => StmtKind::ReadInt(ident),
+        })
+    }
+
+    fn parse_write(&mut self) -> StmtResult {
+        let token = self.next().unwrap();
+        let path = self.expr()?;
+        self.consume(TokenKind::Comma)?;
+        let text = self.expr()?;
+        Ok(match token.kind {
+            TokenKind::WriteFile => StmtKind::WriteFile(path, text),
+            _ => StmtKind::AppendFile(path, text),
+        })
     }
 
     fn parse_check(&mut self) -> StmtResult {
         self.next().unwrap();
         let expr = self.expr()?;
-        self.consume(TokenKind::Newline)?;
-        Ok(Stmt::Check(expr))
+        Ok(StmtKind::Check(expr))
     }
 
     fn parse_commentop(&mut self) -> StmtResult {
         self.next().unwrap();
         let span = self.span()?;
-        self.consume(TokenKind::Newline)?;
-        Ok(Stmt::CommentOp(span))
+        Ok(StmtKind::CommentOp(span))
     }
 
     fn parse_uncomment(&mut self) -> StmtResult {
         self.next().unwrap();
-        let ident = self.ident()?;
-        self.consume(TokenKind::Newline)?;
-        Ok(Stmt::Uncomment(ident))
+        let ident = self.dotted_var()?;
+        Ok(StmtKind::Uncomment(ident))
     }
 
     fn parse_copy(&mut self) -> StmtResult {
         self.next().unwrap();
-        let ident = self.ident()?;
+        let ident = self.dotted_var()?;
         let halfspan = self.halfspan()?;
-        self.consume(TokenKind::Newline)?;
-        Ok(Stmt::Copy(ident, halfspan))
+        Ok(StmtKind::Copy(ident, halfspan))
     }
 
     fn parse_move(&mut self) -> StmtResult {
         self.next().unwrap();
-        let ident = self.ident()?;
+        let ident = self.var()?;
         let halfspan = self.halfspan()?;
-        self.consume(TokenKind::Newline)?;
-        Ok(Stmt::Move(ident, halfspan))
+        Ok(StmtKind::Move(ident, halfspan))
     }
 
-    fn parse_comment(&mut self) -> StmtResult {
+    fn parse_call(&mut self) -> StmtResult {
+        self.next().unwrap();
+        let ident = self.dotted_var()?;
+        Ok(StmtKind::Call(ident))
+    }
+
+    fn parse_include(&mut self) -> StmtResult {
+        self.next().unwrap();
         let token = self.next().unwrap();
+        if token.kind != TokenKind::StringLit {
+            return Err(Self::unexpected(token, "string literal"));
+        }
         let text = self.text(token);
-        let text = &text[1..text.len() - 1];
+        let path = unescape(&text[1..text.len() - 1], token.span)?;
+        let module = if self.peek() == TokenKind::As {
+            self.consume(TokenKind::As)?;
+            Some(self.var()?)
+        } else {
+            None
+        };
+        Ok(StmtKind::Include(path, module))
+    }
 
-        let mut parser = Parser::new(text);
-        let mut stmts = vec![];
-        loop {
-            match parser.peek() {
-                TokenKind::Newline => continue,
-                TokenKind::Eof => break,
-                _ => {
-                    if let Ok(stmt) = parser.parse_stmt() {
-                        stmts.push(stmt);
-                    } else {
-                        return Ok(Stmt::Comment(Comment::Invalid));
-                    }
-                }
+    fn parse_conditional(&mut self, guard: Option<Expr>) -> StmtResult {
+        self.consume(TokenKind::Question)?;
+        let stmt = self.spanned(|parser| match parser.peek() {
+            TokenKind::CommentOp => parser.parse_commentop(),
+            TokenKind::Uncomment => parser.parse_uncomment(),
+            TokenKind::Copy => parser.parse_copy(),
+            TokenKind::Move => parser.parse_move(),
+            TokenKind::Call => parser.parse_call(),
+            _ => {
+                let token = parser.next().unwrap();
+                Err(Self::unexpected(token, "comment operation"))
             }
+        })?;
+        Ok(StmtKind::Conditional(guard, Box::new(stmt)))
+    }
+
+    /// A comment literal on its own is a statement, but it can also start the guard of a conditional
+    fn parse_comment(&mut self) -> StmtResult {
+        // a comment literal on its own pushes itself onto the stack
+        let expr = self.expr()?;
+        match expr.kind {
+            ExprKind::Comment(..) if self.peek() != TokenKind::Question => Ok(StmtKind::Push(expr)),
+            _ => self.parse_conditional(Some(expr)),
         }
+    }
 
-        Ok(Stmt::Comment(if stmts.is_empty() {
+    /// Parse the text of a comment, without its braces.
+    /// This is the only way comments are ever parsed, be it in the source or while running.
+    pub fn comment_body(text: &str) -> Comment {
+        let (lines, mut errors) = Parser::new(text).parse_all();
+        if !errors.is_empty() {
+            Comment::Invalid(errors.swap_remove(0))
+        } else if lines.is_empty() {
             Comment::Empty
         } else {
-            Comment::Valid(stmts)
-        }))
+            let lines = lines.into_iter();
+            Comment::Valid(
+                lines
+                    .map(|(stmt, source)| (stmt, source.to_string()))
+                    .collect(),
+            )
+        }
     }
 }
@@ -0,0 +1,137 @@
+use std::{
+    fs,
+    io::{self, IsTerminal, Write},
+    iter,
+};
+
+use fire_ferrets::{
+    interpreter::{input::Input, Interpreter},
+    lexer::{Lexer, TokenKind},
+};
+
+const HELP: &str = "\
+Enter Ferette statements to run them. Comment literals can span multiple lines.
+Line addresses in comment operations are relative to the current input.
+
+:stack        show the stack, top last
+:vars         show all variables
+:reset        forget all variables and clear the stack
+:load <file>  run a file
+:help         show this message
+:quit         exit the REPL";
+
+/// An interactive session that keeps the same interpreter alive between inputs
+pub struct Repl {
+    interpreter: Interpreter,
+    /// Prompts are only shown when a person is typing, not when input is piped in
+    interactive: bool,
+}
+
+impl Repl {
+    pub fn new(interpreter: Interpreter) -> Self {
+        Self {
+            interpreter,
+            interactive: io::stdin().is_terminal(),
+        }
+    }
+
+    pub fn run(&mut self) {
+        if self.interactive {
+            println!("Ferette REPL, type :help for help");
+        }
+
+        // stdin can't stay locked, `read` statements need it too
+        let mut stdin = Input::Stdin;
+        let mut lines = iter::from_fn(|| stdin.read_line().transpose());
+        while let Some(input) = self.read_input(&mut lines) {
+            let trimmed = input.trim();
+            if let Some(command) = trimmed.strip_prefix(':') {
+                if !self.meta_command(command) {
+                    break;
+                }
+            } else if !trimmed.is_empty() {
+                crate::run(&mut self.interpreter, "<repl>", &input);
+            }
+        }
+    }
+
+    /// Read a line of input, and keep reading while a comment literal is left open.
+    /// Returns `None` at the end of input.
+    fn read_input(&self, lines: &mut impl Iterator<Item = io::Result<String>>) -> Option<String> {
+        let mut input = String::new();
+        loop {
+            self.prompt(if input.is_empty() { "> " } else { "... " });
+            match lines.next() {
+                Some(Ok(line)) => {
+                    input.push_str(&line);
+                    input.push('\n');
+                }
+                // only give back what we have if there's anything at all
+                _ if input.is_empty() => return None,
+                _ => return Some(input),
+            }
+            if !unclosed_comment(&input) {
+                return Some(input);
+            }
+        }
+    }
+
+    fn prompt(&self, prompt: &str) {
+        if self.interactive {
+            print!("{}", prompt);
+            // nothing to be done if stdout is gone
+            let _ = io::stdout().flush();
+        }
+    }
+
+    /// Run a meta command (without the `:`), returns whether to keep going
+    fn meta_command(&mut self, command: &str) -> bool {
+        let (name, arg) = match command.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (command, ""),
+        };
+        match name {
+            "stack" => {
+                for value in self.interpreter.env().stack() {
+                    println!("{}", value.repr());
+                }
+            }
+            "vars" => {
+                for (name, value) in self.interpreter.env().vars() {
+                    println!("{} = {}", name, value.repr());
+                }
+            }
+            "reset" => self.interpreter.reset(),
+            "load" if !arg.is_empty() => match fs::read_to_string(arg) {
+                Ok(contents) => {
+                    crate::run(&mut self.interpreter, arg, &contents);
+                }
+                Err(e) => eprintln!("error: couldn't read {}: {}", arg, e),
+            },
+            "load" => eprintln!("error: :load needs a file name"),
+            "help" => println!("{}", HELP),
+            "quit" | "q" => return false,
+            _ => eprintln!("error: unknown command :{}, try :help", name),
+        }
+        true
+    }
+}
+
+/// Check whether `input` has a `{` without a matching `}`, ignoring string literals
+fn unclosed_comment(input: &str) -> bool {
+    Lexer::new(input).any(|token| token.kind == TokenKind::UnclosedComment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unclosed_comment;
+
+    #[test]
+    fn test_unclosed_comment() {
+        assert!(!unclosed_comment("print 1\n"));
+        assert!(unclosed_comment("{print 1\n"));
+        assert!(!unclosed_comment("{print 1\nprint 2}\n"));
+        assert!(!unclosed_comment("print \"{\"\n"));
+        assert!(unclosed_comment("{print \"}\\\"\"\n"));
+    }
+}
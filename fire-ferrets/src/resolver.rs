@@ -0,0 +1,402 @@
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    error::FerError,
+    parser::{Comment, Expr, ExprKind, Stmt, StmtKind, Var},
+};
+
+/// Where the value of a variable is kept in the environment
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Slot(pub u32);
+
+/// Hands out a slot for every distinct variable name
+#[derive(Debug)]
+pub struct Interner {
+    /// Different for every interner, since the same name can have a different slot in each
+    id: u64,
+    slots: HashMap<String, Slot>,
+    names: Vec<String>,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            slots: HashMap::new(),
+            names: vec![],
+        }
+    }
+}
+
+impl Interner {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The slot for `name`, which is the same every time it's asked for
+    pub fn intern(&mut self, name: &str) -> Slot {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+        let slot = Slot(self.names.len() as u32);
+        self.slots.insert(name.to_string(), slot);
+        self.names.push(name.to_string());
+        slot
+    }
+
+    /// The slot for `name`, if it has one
+    pub fn get(&self, name: &str) -> Option<Slot> {
+        self.slots.get(name).copied()
+    }
+
+    pub fn name(&self, slot: Slot) -> &str {
+        &self.names[slot.0 as usize]
+    }
+}
+
+/// Gives every variable a slot in the environment before it's used,
+/// and reports variables that are used but never defined
+pub struct Resolver<'a> {
+    interner: &'a mut Interner,
+    /// The variables that might exist, or `None` if uses aren't being checked
+    known: Option<HashSet<String>>,
+    /// The undefined variables that were only used by statements under a `?`
+    warned: HashSet<String>,
+    undefined: Undefined,
+}
+
+/// Variables that are used but never defined
+#[derive(Debug, Default)]
+pub struct Undefined {
+    /// Uses that will fail whenever they're reached
+    pub errors: Vec<FerError>,
+    /// Uses by statements under a `?`, which only fail if the condition is true
+    pub warnings: Vec<FerError>,
+}
+
+impl<'a> Resolver<'a> {
+    /// A resolver that only hands out slots
+    pub fn new(interner: &'a mut Interner) -> Self {
+        Self {
+            interner,
+            known: None,
+            warned: HashSet::new(),
+            undefined: Undefined::default(),
+        }
+    }
+
+    /// A resolver that also reports uses of variables that aren't `defined` already
+    /// and that no statement in `stmts` defines, not even inside of a comment
+    pub fn checking<'s>(
+        interner: &'a mut Interner,
+        stmts: impl IntoIterator<Item = &'s Stmt>,
+        mut defined: HashSet<String>,
+    ) -> Self {
+        let mut resolver = Self::new(interner);
+        // a plain `include` can define anything, so there's no telling what's undefined
+        if stmts
+            .into_iter()
+            .all(|stmt| definitions_in(stmt, &mut defined))
+        {
+            resolver.known = Some(defined);
+        }
+        resolver
+    }
+
+    /// Give every variable used by `stmt` a slot.
+    /// Only uses outside of comments are checked, a comment might be run where the variable exists.
+    /// The code in comment literals isn't resolved here either. It was parsed along with the
+    /// literal, and `CommentValue::lines` resolves it the first time it's run with an interner.
+    /// Undefined variables used by the statement under a `?` are only warned about.
+    pub fn resolve(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Conditional(guard, stmt) => {
+                if let Some(guard) = guard {
+                    visit_expr_vars(guard, &mut |var| self.use_var(var, false));
+                }
+                visit_vars(stmt, &mut |var| self.use_var(var, true));
+            }
+            _ => visit_vars(stmt, &mut |var| self.use_var(var, false)),
+        }
+    }
+
+    fn use_var(&mut self, var: &mut Var, guarded: bool) {
+        var.slot = Some(self.interner.intern(var.root()));
+        let known = match &mut self.known {
+            Some(known) => known,
+            None => return,
+        };
+        let undefined = || FerError::Runtime(var.span, format!("{} is undefined", var.root()));
+        // each variable is only reported once, but a warning doesn't stop an error later on
+        if !guarded {
+            if known.insert(var.root().to_string()) {
+                self.undefined.errors.push(undefined());
+            }
+        } else if !known.contains(var.root()) && self.warned.insert(var.root().to_string()) {
+            self.undefined.warnings.push(undefined());
+        }
+    }
+
+    /// The undefined variables that were found
+    pub fn finish(self) -> Undefined {
+        self.undefined
+    }
+}
+
+/// Add the names of the variables `stmt` defines to `definitions`, including in comments.
+/// Returns `false` if there's no telling what it defines.
+fn definitions_in(stmt: &Stmt, definitions: &mut HashSet<String>) -> bool {
+    if !exprs(stmt)
+        .into_iter()
+        .all(|expr| expr_definitions(expr, definitions))
+    {
+        return false;
+    }
+    match &stmt.kind {
+        StmtKind::Set(var, _) | StmtKind::Read(var) | StmtKind::ReadInt(var) => {
+            definitions.insert(var.name.clone());
+        }
+        StmtKind::Include(_, Some(module)) => {
+            definitions.insert(module.name.clone());
+        }
+        StmtKind::Include(_, None) => return false,
+        StmtKind::Conditional(_, stmt) => return definitions_in(stmt, definitions),
+        _ => {}
+    }
+    true
+}
+
+/// Like `definitions_in`, for the comment literals in `expr`
+fn expr_definitions(expr: &Expr, definitions: &mut HashSet<String>) -> bool {
+    match &expr.kind {
+        ExprKind::Comment(comment) => comment_definitions(comment.body(), definitions),
+        _ => subexprs(expr)
+            .into_iter()
+            .all(|expr| expr_definitions(expr, definitions)),
+    }
+}
+
+/// The expressions `expr` is made of
+fn subexprs(expr: &Expr) -> Vec<&Expr> {
+    match &expr.kind {
+        ExprKind::BinaryOp(_, lhs, rhs) | ExprKind::Index(lhs, rhs) => vec![lhs, rhs],
+        ExprKind::UnaryOp(_, expr) | ExprKind::StackIndex(expr) => vec![expr],
+        ExprKind::Call(_, items) | ExprKind::List(items) => items.iter().collect(),
+        ExprKind::Map(entries) => entries
+            .iter()
+            .flat_map(|(key, value)| [key, value])
+            .collect(),
+        ExprKind::Slice(expr, from, to) => {
+            let bounds = from.iter().chain(to).map(|bound| &**bound);
+            iter::once(&**expr).chain(bounds).collect()
+        }
+        ExprKind::Literal(_)
+        | ExprKind::Ident(_)
+        | ExprKind::Comment(..)
+        | ExprKind::Pop
+        | ExprKind::Depth
+        | ExprKind::Peek => vec![],
+    }
+}
+
+fn comment_definitions(comment: &Comment, definitions: &mut HashSet<String>) -> bool {
+    match comment {
+        Comment::Valid(stmts) => stmts
+            .iter()
+            .all(|(stmt, _)| definitions_in(stmt, definitions)),
+        Comment::Invalid(_) | Comment::Empty => true,
+    }
+}
+
+/// The expressions that are part of `stmt` itself, not of the statement a conditional guards
+fn exprs(stmt: &Stmt) -> Vec<&Expr> {
+    match &stmt.kind {
+        StmtKind::Set(_, expr)
+        | StmtKind::Append(_, expr)
+        | StmtKind::Remove(_, expr)
+        | StmtKind::Push(expr)
+        | StmtKind::Check(expr)
+        | StmtKind::Pick(expr)
+        | StmtKind::Print(expr)
+        | StmtKind::Conditional(Some(expr), _) => vec![expr],
+        StmtKind::SetIndex(_, path, expr) => path.iter().chain([expr]).collect(),
+        StmtKind::WriteFile(path, text) | StmtKind::AppendFile(path, text) => vec![path, text],
+        _ => vec![],
+    }
+}
+
+/// Call `f` on every variable used by `stmt`, outside of comment literals
+fn visit_vars(stmt: &mut Stmt, f: &mut impl FnMut(&mut Var)) {
+    match &mut stmt.kind {
+        StmtKind::Set(var, expr) => {
+            visit_expr_vars(expr, f);
+            f(var);
+        }
+        StmtKind::SetIndex(var, path, expr) => {
+            for index in path {
+                visit_expr_vars(index, f);
+            }
+            visit_expr_vars(expr, f);
+            f(var);
+        }
+        StmtKind::Append(var, expr) | StmtKind::Remove(var, expr) => {
+            visit_expr_vars(expr, f);
+            f(var);
+        }
+        StmtKind::Push(expr)
+        | StmtKind::Check(expr)
+        | StmtKind::Pick(expr)
+        | StmtKind::Print(expr) => visit_expr_vars(expr, f),
+        StmtKind::WriteFile(path, text) | StmtKind::AppendFile(path, text) => {
+            visit_expr_vars(path, f);
+            visit_expr_vars(text, f);
+        }
+        StmtKind::Read(var)
+        | StmtKind::ReadInt(var)
+        | StmtKind::Uncomment(var)
+        | StmtKind::Copy(var, _)
+        | StmtKind::Move(var, _)
+        | StmtKind::Call(var)
+        | StmtKind::Include(_, Some(var)) => f(var),
+        StmtKind::Conditional(guard, stmt) => {
+            if let Some(guard) = guard {
+                visit_expr_vars(guard, f);
+            }
+            visit_vars(stmt, f);
+        }
+        StmtKind::Pop
+        | StmtKind::Dup
+        | StmtKind::Swap
+        | StmtKind::Over
+        | StmtKind::Rot
+        | StmtKind::Drop
+        | StmtKind::Clear
+        | StmtKind::CommentOp(_)
+        | StmtKind::Include(_, None) => {}
+    }
+}
+
+fn visit_expr_vars(expr: &mut Expr, f: &mut impl FnMut(&mut Var)) {
+    match &mut expr.kind {
+        ExprKind::Ident(var) => f(var),
+        ExprKind::BinaryOp(_, lhs, rhs) | ExprKind::Index(lhs, rhs) => {
+            visit_expr_vars(lhs, f);
+            visit_expr_vars(rhs, f);
+        }
+        ExprKind::UnaryOp(_, expr) | ExprKind::StackIndex(expr) => visit_expr_vars(expr, f),
+        ExprKind::Call(_, items) | ExprKind::List(items) => {
+            for item in items {
+                visit_expr_vars(item, f);
+            }
+        }
+        ExprKind::Map(entries) => {
+            for (key, value) in entries {
+                visit_expr_vars(key, f);
+                visit_expr_vars(value, f);
+            }
+        }
+        ExprKind::Slice(expr, from, to) => {
+            visit_expr_vars(expr, f);
+            for bound in from.iter_mut().chain(to) {
+                visit_expr_vars(bound, f);
+            }
+        }
+        ExprKind::Literal(_)
+        | ExprKind::Comment(..)
+        | ExprKind::Pop
+        | ExprKind::Depth
+        | ExprKind::Peek => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interner, Resolver, Slot};
+    use crate::{
+        error::FerError,
+        parser::{Parser, StmtKind},
+    };
+
+    /// The messages of the undefined variable errors and warnings in `input`
+    fn undefined(input: &str, defined: &[&str]) -> (Vec<String>, Vec<String>) {
+        let (lines, errors) = Parser::new(input).parse_all();
+        assert!(errors.is_empty());
+        let mut stmts: Vec<_> = lines.into_iter().map(|(stmt, _)| stmt).collect();
+        let defined = defined.iter().map(|name| name.to_string()).collect();
+        let mut interner = Interner::default();
+        let mut resolver = Resolver::checking(&mut interner, stmts.iter(), defined);
+        for stmt in &mut stmts {
+            resolver.resolve(stmt);
+        }
+        let messages = |errors: Vec<FerError>| {
+            errors
+                .into_iter()
+                .map(|e| match e {
+                    FerError::Runtime(_, msg) => msg,
+                    e => panic!("unexpected error {:?}", e),
+                })
+                .collect()
+        };
+        let undefined = resolver.finish();
+        (messages(undefined.errors), messages(undefined.warnings))
+    }
+
+    fn check(input: &str, defined: &[&str]) -> Vec<String> {
+        undefined(input, defined).0
+    }
+
+    #[test]
+    fn test_interner() {
+        let mut interner = Interner::default();
+        let x = interner.intern("x");
+        assert_eq!(interner.intern("y"), Slot(1));
+        assert_eq!(interner.intern("x"), x);
+        assert_eq!(interner.get("y"), Some(Slot(1)));
+        assert_eq!(interner.get("z"), None);
+        assert_eq!(interner.name(x), "x");
+    }
+
+    #[test]
+    fn test_slots() {
+        let mut stmt = Parser::new("set y x + y").parse_stmt().unwrap();
+        let mut interner = Interner::default();
+        Resolver::new(&mut interner).resolve(&mut stmt);
+        match stmt.kind {
+            StmtKind::Set(var, _) => assert_eq!(var.slot, Some(interner.intern("y"))),
+            _ => unreachable!(),
+        }
+        assert_eq!(interner.get("x"), Some(Slot(0)));
+    }
+
+    #[test]
+    fn test_undefined() {
+        assert_eq!(
+            check("print x + x\ncall m.f", &[]),
+            vec!["x is undefined", "m is undefined"]
+        );
+        assert!(check("print x\nset x 1", &[]).is_empty());
+        assert!(check("print x", &["x"]).is_empty());
+        // comments can define variables, but what they use isn't checked
+        assert!(check("{set x 1\nprint y}\nprint x", &[]).is_empty());
+        assert!(check("set f {set x 1}\ncall f\nprint x", &[]).is_empty());
+        assert!(check("push [{read x}]\nprint x", &[]).is_empty());
+        assert!(check("include \"lib.fer\"\nprint x", &[]).is_empty());
+        assert!(check("include \"lib.fer\" as lib\ncall lib.f", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_guarded_uses_warn() {
+        let (errors, warnings) = undefined("push 0\n? call f\nx > 0 ? uncomment y", &[]);
+        assert_eq!(errors, vec!["x is undefined"]);
+        assert_eq!(warnings, vec!["f is undefined", "y is undefined"]);
+        // a guarded use doesn't hide an unguarded one
+        let (errors, warnings) = undefined("? call f\ncall f\n? call f", &[]);
+        assert_eq!(errors, vec!["f is undefined"]);
+        assert_eq!(warnings, vec!["f is undefined"]);
+    }
+}
@@ -0,0 +1,93 @@
+//! Run every test program on both the tree-walking interpreter and the bytecode VM,
+//! and check that they behave exactly the same
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+
+/// Enough input for the programs that read some
+const INPUT: &str = "Ferris\n 3 \nsecond\n";
+
+/// Run `program` in `tests/programs` with `args`, feeding it `INPUT` on stdin
+fn run(program: &Path, args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fire-ferrets"))
+        .args(args)
+        .arg(program)
+        .current_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // programs that don't read their input may be done before it's written
+    let _ = child.stdin.take().unwrap().write_all(INPUT.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+/// Every `.fer` file directly inside `dir`
+fn programs(dir: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+    let mut programs: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "fer"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+fn check_same(program: &Path, args: &[&str]) {
+    let tree = run(program, args);
+    let vm = run(program, &[args, &["--vm"]].concat());
+    let name = program.display();
+    assert_eq!(
+        String::from_utf8_lossy(&tree.stdout),
+        String::from_utf8_lossy(&vm.stdout),
+        "stdout of {}",
+        name
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&tree.stderr),
+        String::from_utf8_lossy(&vm.stderr),
+        "stderr of {}",
+        name
+    );
+    assert_eq!(
+        tree.status.code(),
+        vm.status.code(),
+        "exit code of {}",
+        name
+    );
+}
+
+#[test]
+fn test_programs() {
+    let programs = programs("tests/programs");
+    assert!(programs.len() > 30);
+    for program in &programs {
+        check_same(program, &[]);
+    }
+}
+
+#[test]
+fn test_examples() {
+    for program in &programs("examples") {
+        check_same(program, &[]);
+    }
+}
+
+#[test]
+fn test_options() {
+    for program in &["overflow.fer", "floats.fer", "files.fer"] {
+        for args in &[
+            &["--overflow=wrapping"][..],
+            &["--overflow=bigint"],
+            &["--no-fs"],
+        ] {
+            check_same(Path::new(program), args);
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Run the interpreter on `tests/programs/<name>.fer`, returning stdout and stderr
+fn run(name: &str) -> (String, String) {
+    run_with_args(name, &[])
+}
+
+/// Like `run`, but passes `args` to the interpreter before the file name
+fn run_with_args(name: &str, args: &[&str]) -> (String, String) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/programs")
+        .join(name)
+        .with_extension("fer");
+    let output = Command::new(env!("CARGO_BIN_EXE_fire-ferrets"))
+        .args(args)
+        .arg(path)
+        .output()
+        .unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+/// Check that the program prints exactly what's in `tests/programs/<name>.out`
+fn check_output(name: &str) {
+    let expected = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/programs")
+        .join(name)
+        .with_extension("out");
+    let expected = fs::read_to_string(expected).unwrap();
+    let (stdout, stderr) = run(name);
+    assert_eq!(stderr, "");
+    assert_eq!(stdout, expected);
+}
+
+/// Check that the program fails with an error containing `msg`
+fn check_error(name: &str, msg: &str) {
+    let (_, stderr) = run(name);
+    assert!(stderr.contains(msg), "expected {:?} in {:?}", msg, stderr);
+}
+
+#[test]
+fn test_examples() {
+    for name in &[
+        "../../examples/hello_world",
+        "../../examples/variables_and_stack",
+    ] {
+        let (_, stderr) = run(name);
+        assert_eq!(stderr, "");
+    }
+}
+
+#[test]
+fn test_comment_literal() {
+    check_output("comment_literal");
+}
+
+#[test]
+fn test_comment_expr() {
+    check_output("comment_expr");
+}
+
+#[test]
+fn test_comment_out() {
+    check_output("comment_out");
+}
+
+#[test]
+fn test_copy_move() {
+    check_output("copy_move");
+}
+
+#[test]
+fn test_move_consumes_variable() {
+    check_error("move_consumes", "c is undefined");
+}
+
+#[test]
+fn test_span_out_of_bounds() {
+    check_error("out_of_bounds", "Line -1 is out of bounds");
+}
+
+#[test]
+fn test_conditional_loop() {
+    check_output("conditional");
+}
+
+#[test]
+fn test_check_branch() {
+    check_output("check_branch");
+}
+
+#[test]
+fn test_conditional_requires_comment_op() {
+    check_error(
+        "conditional_bad_op",
+        "Expected comment operation, got print",
+    );
+}
+
+#[test]
+fn test_call() {
+    check_output("call");
+}
+
+#[test]
+fn test_infinite_recursion() {
+    check_error(
+        "infinite_recursion",
+        "Maximum call depth of 1000 exceeded while calling f",
+    );
+}
+
+#[test]
+fn test_diagnostic() {
+    check_error("undefined", "error: y is undefined\n");
+    check_error("undefined", "undefined.fer:2:7\n");
+    check_error("undefined", "2 | print y\n  |       ^\n");
+}
+
+#[test]
+fn test_all_syntax_errors_reported() {
+    check_error("syntax_errors", "syntax_errors.fer:2:5");
+    check_error("syntax_errors", "syntax_errors.fer:4:9");
+    check_error("syntax_errors", "aborting due to 2 syntax error(s)");
+    let (stdout, stderr) = run("syntax_errors");
+    assert_eq!(stdout, "");
+    // no blank lines between diagnostics, same as for runtime errors
+    assert!(!stderr.contains("\n\n"));
+}
+
+#[test]
+fn test_nested_comments() {
+    check_output("nested_comments");
+}
+
+#[test]
+fn test_unbalanced_braces() {
+    check_error(
+        "unclosed_comment",
+        "This { is never closed, comment literals need a matching }",
+    );
+    check_error("unclosed_comment", "unclosed_comment.fer:2:7");
+    check_error("stray_brace", "stray_brace.fer:1:8");
+    check_error("stray_brace", "stray_brace.fer:2:10");
+    check_error("stray_brace", "aborting due to 2 syntax error(s)");
+}
+
+/// Run the REPL with `input` piped into it, returning stdout and stderr
+fn run_repl(input: &str) -> (String, String) {
+    run_with_stdin(&[], input)
+}
+
+/// Run the interpreter with `args` in `tests/programs`, feeding it `input` on stdin
+fn run_with_stdin(args: &[&str], input: &str) -> (String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fire-ferrets"))
+        .args(args)
+        .current_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_repl_keeps_state() {
+    let (stdout, stderr) = run_repl(
+        "set a 1\npush a + 1\n{print a\nprint pop}\nset c pop\n:vars\n:stack\ncall c\n:stack\n",
+    );
+    assert_eq!(stderr, "");
+    assert_eq!(stdout, "a = 1\nc = {print a\nprint pop}\n2\n1\n2\n");
+}
+
+#[test]
+fn test_repl_reset_and_load() {
+    let (stdout, stderr) = run_repl("set a 1\n:reset\n:vars\n:load call.fer\nprint a\n");
+    assert!(stderr.contains("a is undefined"), "{:?}", stderr);
+    assert!(stdout.starts_with("120\n"), "{:?}", stdout);
+}
+
+#[test]
+fn test_repl_errors_are_not_fatal() {
+    let (stdout, stderr) = run_repl("print x\nset 1\n:nope\nprint 1\n");
+    assert!(stderr.contains("x is undefined"));
+    assert!(stderr.contains("unknown command :nope"));
+    assert_eq!(stdout, "1\n");
+}
+
+#[test]
+fn test_strings() {
+    check_output("strings");
+}
+
+#[test]
+fn test_string_type_error() {
+    check_error("string_type_error", "Cannot apply + to string and integer");
+}
+
+#[test]
+fn test_bad_escape() {
+    check_error("bad_escape", "Unknown escape sequence \\q");
+}
+
+#[test]
+fn test_truthiness() {
+    check_output("truthiness");
+}
+
+#[test]
+fn test_division_by_zero() {
+    let (_, stderr) = run("division_by_zero");
+    assert!(stderr.contains("error: Division by zero"), "{}", stderr);
+    assert!(stderr.contains("division_by_zero.fer:3:7"), "{}", stderr);
+}
+
+#[test]
+fn test_integer_overflow() {
+    let (stdout, stderr) = run("overflow");
+    assert_eq!(stdout, "");
+    assert!(
+        stderr.contains("error: Integer overflow in +"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("overflow.fer:2:7"), "{}", stderr);
+}
+
+#[test]
+fn test_overflow_modes() {
+    for (mode, expected) in &[
+        ("wrapping", "-9223372036854775808\n9223372036854775807\n"),
+        ("saturating", "9223372036854775807\n9223372036854775806\n"),
+        ("bigint", "9223372036854775808\n9223372036854775807\n"),
+    ] {
+        let (stdout, stderr) = run_with_args("overflow", &[&format!("--overflow={}", mode)]);
+        assert_eq!(stderr, "");
+        assert_eq!(&stdout, expected, "--overflow={}", mode);
+    }
+    let (_, stderr) = run_with_args("overflow", &["--overflow=modular"]);
+    assert!(
+        stderr.contains("Unknown overflow mode 'modular'"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_floats() {
+    check_output("floats");
+}
+
+#[test]
+fn test_bad_conversion() {
+    check_error("bad_conversion", "Cannot convert \"12.5kg\" to a float");
+}
+
+#[test]
+fn test_stack_ops() {
+    check_output("stack_ops");
+}
+
+#[test]
+fn test_stack_underflow() {
+    check_error(
+        "stack_underflow",
+        "Stack underflow: rot needs 3 value(s), but the stack has 2",
+    );
+}
+
+#[test]
+fn test_peek() {
+    check_output("peek");
+}
+
+#[test]
+fn test_stack_index_out_of_bounds() {
+    check_error(
+        "stack_index",
+        "stack[1] is out of bounds, the stack has 1 value(s)",
+    );
+}
+
+#[test]
+fn test_lists() {
+    check_output("lists");
+}
+
+#[test]
+fn test_list_index_out_of_bounds() {
+    check_error("list_index", "Index 3 is out of bounds for length 3");
+}
+
+#[test]
+fn test_maps() {
+    check_output("maps");
+}
+
+#[test]
+fn test_missing_key() {
+    check_error("missing_key", "The map has no key \"b\"");
+}
+
+#[test]
+fn test_read_input() {
+    let (stdout, stderr) = run_with_stdin(&["read.fer"], "Ferris\n 3 \nsecond\r\n");
+    assert_eq!(stderr, "");
+    assert_eq!(
+        stdout,
+        "Hello, Ferris!\nFerris Ferris Ferris \nsecond\ntrue\n"
+    );
+
+    let (_, stderr) = run_with_stdin(&["read.fer"], "Ferris\nthree\n");
+    assert!(
+        stderr.contains("Expected an integer, got \"three\""),
+        "{}",
+        stderr
+    );
+    let (_, stderr) = run_with_stdin(&["read.fer"], "");
+    assert!(stderr.contains("Unexpected end of input"), "{}", stderr);
+}
+
+#[test]
+fn test_repl_shares_stdin() {
+    let (stdout, stderr) = run_repl("read name\nFerris\nprint name\n");
+    assert_eq!(stderr, "");
+    assert_eq!(stdout, "Ferris\n");
+}
+
+#[test]
+fn test_file_io() {
+    let (stdout, stderr) = run_with_stdin(&["files.fer"], "");
+    assert_eq!(stdout, "true\nread name\nfalse\nfalse\n");
+    assert!(stderr.contains("Couldn't read missing.txt"), "{}", stderr);
+    assert!(stderr.contains("files.fer:5:7"), "{}", stderr);
+
+    let (stdout, stderr) = run_with_stdin(&["--no-fs", "files.fer"], "");
+    assert_eq!(stdout, "");
+    assert!(
+        stderr.contains("Filesystem access is disabled"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_missing_input_file() {
+    let (_, stderr) = run("does_not_exist");
+    assert!(stderr.starts_with("error: couldn't read "), "{}", stderr);
+}
+
+#[test]
+fn test_include() {
+    check_output("include");
+}
+
+#[test]
+fn test_circular_include() {
+    check_error("include_cycle", "Circular include: ");
+    check_error("include_cycle", "cycle_a.fer -> ");
+    check_error("include_cycle", "modules/cycle_b.fer:2:1");
+}
+
+#[test]
+fn test_included_file_diagnostics() {
+    let (stdout, stderr) = run("include_runtime_error");
+    assert_eq!(stdout, "main\nbefore\n");
+    assert!(stderr.contains("error: Division by zero"), "{}", stderr);
+    assert!(
+        stderr.contains("modules/runtime_error.fer:2:7"),
+        "{}",
+        stderr
+    );
+
+    check_error("include_syntax_error", "modules/syntax_error.fer:1:6");
+    check_error("include_syntax_error", "modules/syntax_error.fer:3:5");
+}
+
+#[test]
+fn test_comment_runtime_error() {
+    let (stdout, stderr) = run("comment_runtime_error");
+    assert_eq!(stdout, "in comment\n");
+    assert!(stderr.contains("<comment>:1:7"), "{}", stderr);
+    assert!(
+        stderr.contains("this line was inserted by a comment operation"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_self_modifying() {
+    check_output("self_modifying");
+    let (stdout, stderr) = run_with_args("self_modifying", &["--vm"]);
+    assert_eq!(stderr, "");
+    assert_eq!(
+        stdout,
+        "[\"total\": 6]\ntrue\nfalse\n0\n5\n[\"still here\"]\n"
+    );
+}
+
+#[test]
+fn test_undefined_before_running() {
+    let (stdout, stderr) = run("undefined_static");
+    assert_eq!(stdout, "");
+    assert!(stderr.contains("undefined_static.fer:6:11"), "{}", stderr);
+    assert!(stderr.contains("undefined_static.fer:7:6"), "{}", stderr);
+    assert!(
+        stderr.contains("aborting due to 2 undefined variable(s)"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("x is undefined"), "{}", stderr);
+}
+
+#[test]
+fn test_guarded_undefined_only_warns() {
+    let (stdout, stderr) = run("guarded_undefined");
+    assert_eq!(stdout, "ran\n");
+    assert!(stderr.contains("warning: f is undefined"), "{}", stderr);
+    assert!(stderr.contains("guarded_undefined.fer:2:8"), "{}", stderr);
+}